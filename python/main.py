@@ -64,7 +64,7 @@ def search(url: str):
     print(f"In {took*1000:.2f}ms")
     data = res.json()
     print(data)
-    for result in data[:10]:
+    for result in data["results"][:10]:
         words = [colorize(word['word'], "red") if word["matched"] else word['word'] for word in result['words']]
         print(" ".join(words) + "\n")
 
@@ -1,9 +1,15 @@
 use config::{Config, File};
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::{hsk_file::HskResult, searcher::normalize_word, utils::Mutated};
+use crate::{
+    hsk_file::HskResult,
+    searcher::{normalize_word, Map},
+    stemmer::Stemmer,
+    synonyms::Synonyms,
+    utils::Mutated,
+};
 
 // store my defaults from `config.toml` in the binary
 // try to read from the default path, if i can't
@@ -22,8 +28,10 @@ const DEFAULT_REMOVE_STOP_WORDS: bool = true;
 const DEFAULT_ALLOW_PAGE_SIZE_OVERWRITE: bool = true;
 const DEFAULT_ALLOW_CONTEXT_SIZE_OVERWRITE: bool = true;
 const DEFAULT_ALLOW_REMOVE_STOP_WORDS_OVERWRITE: bool = true;
+const DEFAULT_ALLOW_WORD_DISTANCE_OVERWRITE: bool = true;
 const DEFAULT_WORD_DISTANCE: usize = 2;
 const DEFAULT_WORD_DISTANCE_WITH_STOP_WORDS_REMOVED: usize = 5;
+const DEFAULT_FUZZY_DISTANCE: usize = 2;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -33,11 +41,48 @@ pub struct AppConfig {
     // Add other config options here
     pub remove_stop_words: bool,
     stop_words_file: Option<PathBuf>,
+    /// Regular expressions (one per line) for the endings that stemming removes from words,
+    /// `word_endings.txt` in the config directory when not given
+    word_endings_file: Option<PathBuf>,
+    /// Groups of interchangeable words and phrases (see [`Synonyms`]), `synonyms.txt` in the config
+    /// directory when not given
+    synonyms_file: Option<PathBuf>,
     pub allow_page_size_overwrite: bool,
     pub allow_context_size_overwrite: bool,
     pub allow_remove_stop_words_overwrite: bool,
+    #[serde(default = "default_allow_word_distance_overwrite")]
+    pub allow_word_distance_overwrite: bool,
     pub word_distance: usize,
     pub word_distance_with_stop_words_removed: usize,
+    /// The most letters that can differ for a fuzzy match
+    #[serde(default = "default_fuzzy_distance")]
+    pub fuzzy_distance: usize,
+    /// Named sets of transcripts that can be searched on their own
+    #[serde(default)]
+    pub collections: Map<String, CollectionConfig>,
+}
+
+/**
+A named set of transcripts, configured like
+```toml
+[collections.sermons]
+path = "sermons"
+context_size = 30
+stop_words_file = "/home/<your_username>/.config/heurisko/sermon_stop_words.txt"
+```
+- Any setting that is left out uses the value from the rest of the config
+*/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CollectionConfig {
+    /// A sub-directory of the data directory, or a separate data directory (when absolute)
+    pub path: PathBuf,
+    pub page_size: Option<usize>,
+    pub context_size: Option<usize>,
+    pub remove_stop_words: Option<bool>,
+    pub word_distance: Option<usize>,
+    pub word_distance_with_stop_words_removed: Option<usize>,
+    /// Used instead of the config's stop words when searching this collection
+    pub stop_words_file: Option<PathBuf>,
 }
 
 impl AppConfig {
@@ -47,6 +92,8 @@ impl AppConfig {
         // Create data dir if it doesn't exist
         std::fs::create_dir_all(&data_dir)?;
         let stop_words_file = get_stop_words_file_path();
+        let synonyms_file = get_synonyms_file_path();
+        let word_endings_file = get_word_endings_file_path();
         // .ok_or_else(|| String::from("Could not determine stop words directory"))?;
         Ok(Self {
             data_dir,
@@ -54,11 +101,16 @@ impl AppConfig {
             context_size: DEFAULT_CONTEXT_SIZE,
             remove_stop_words: DEFAULT_REMOVE_STOP_WORDS,
             stop_words_file,
+            word_endings_file,
+            synonyms_file,
             allow_page_size_overwrite: DEFAULT_ALLOW_PAGE_SIZE_OVERWRITE,
             allow_context_size_overwrite: DEFAULT_ALLOW_CONTEXT_SIZE_OVERWRITE,
             allow_remove_stop_words_overwrite: DEFAULT_ALLOW_REMOVE_STOP_WORDS_OVERWRITE,
+            allow_word_distance_overwrite: DEFAULT_ALLOW_WORD_DISTANCE_OVERWRITE,
             word_distance: DEFAULT_WORD_DISTANCE,
             word_distance_with_stop_words_removed: DEFAULT_WORD_DISTANCE_WITH_STOP_WORDS_REMOVED,
+            fuzzy_distance: DEFAULT_FUZZY_DISTANCE,
+            collections: Map::new(),
         })
     }
 
@@ -67,14 +119,97 @@ impl AppConfig {
     }
 
     pub fn stop_words(&self) -> Option<Vec<String>> {
-        let path = self.stop_words_file.as_ref()?.as_path();
+        read_stop_words(self.stop_words_file.as_ref()?)
+    }
+
+    /// Uses the built-in English stemmer if there is no (readable) word endings file
+    pub fn stemmer(&self) -> Stemmer {
+        self.word_endings_file
+            .clone()
+            .or_else(get_word_endings_file_path)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|word_endings| Stemmer::new(&word_endings))
+            .unwrap_or_default()
+    }
+
+    pub fn synonyms(&self) -> Option<Synonyms> {
+        let path = self.synonyms_file.clone().or_else(get_synonyms_file_path)?;
         let contents = std::fs::read_to_string(path).ok()?;
-        Some(
-            contents
-                .split_whitespace()
-                .map(|word| normalize_word(word))
-                .collect(),
-        )
+        Some(Synonyms::new(&contents))
+    }
+
+    /// The stop words of a collection, if it has its own
+    pub fn collection_stop_words(&self, collection: &str) -> Option<Vec<String>> {
+        read_stop_words(self.collections.get(collection)?.stop_words_file.as_ref()?)
+    }
+
+    /**
+    The settings a search falls back to when the request doesn't give them, and whether the request
+    can give them
+    - A collection's own settings are used over the rest of the config's
+    - `word_distance_with_stop_words_removed` is overwritten along with `word_distance`
+    */
+    pub fn parameters(&self, collection: Option<&str>) -> Parameters {
+        let collection = collection.and_then(|name| self.collections.get(name));
+        Parameters {
+            page_size: OverwritableParameter::new(
+                collection
+                    .and_then(|c| c.page_size)
+                    .unwrap_or(self.page_size),
+                self.allow_page_size_overwrite,
+            ),
+            context_size: OverwritableParameter::new(
+                collection
+                    .and_then(|c| c.context_size)
+                    .unwrap_or(self.context_size),
+                self.allow_context_size_overwrite,
+            ),
+            remove_stop_words: OverwritableParameter::new(
+                collection
+                    .and_then(|c| c.remove_stop_words)
+                    .unwrap_or(self.remove_stop_words),
+                self.allow_remove_stop_words_overwrite,
+            ),
+            word_distance: OverwritableParameter::new(
+                collection
+                    .and_then(|c| c.word_distance)
+                    .unwrap_or(self.word_distance),
+                self.allow_word_distance_overwrite,
+            ),
+            word_distance_with_stop_words_removed: OverwritableParameter::new(
+                collection
+                    .and_then(|c| c.word_distance_with_stop_words_removed)
+                    .unwrap_or(self.word_distance_with_stop_words_removed),
+                self.allow_word_distance_overwrite,
+            ),
+        }
+    }
+
+    /// Transcripts whose path is (or is nested under) this prefix belong to the collection
+    pub fn collection_prefix(&self, collection: &str) -> Option<String> {
+        let path = &self.collections.get(collection)?.path;
+        let prefix = match path.strip_prefix(&self.data_dir) {
+            Ok(relative_path) => relative_path.to_path_buf(),
+            Err(_) if path.is_absolute() => return Some(separate_collection_prefix(collection)),
+            Err(_) => path.clone(),
+        };
+        Some(prefix.to_string_lossy().trim_matches('/').to_string())
+    }
+
+    /**
+    Every directory that transcripts are loaded from, with the prefix given to their transcript paths
+    - The data directory has no prefix
+    - Collections with a separate data directory are prefixed by `/` and their name (see
+      [`separate_collection_prefix`]), which no path relative to the data directory can start with
+    */
+    pub fn transcript_roots(&self) -> Vec<(String, PathBuf)> {
+        let mut roots = vec![(String::new(), self.data_dir())];
+        for (name, collection) in self.collections.iter() {
+            if collection.path.is_absolute() && !collection.path.starts_with(&self.data_dir) {
+                roots.push((separate_collection_prefix(name), collection.path.clone()));
+            }
+        }
+        roots
     }
 
     pub fn page_size(&self) -> usize {
@@ -90,6 +225,24 @@ impl AppConfig {
     }
 }
 
+fn default_allow_word_distance_overwrite() -> bool {
+    DEFAULT_ALLOW_WORD_DISTANCE_OVERWRITE
+}
+
+fn default_fuzzy_distance() -> usize {
+    DEFAULT_FUZZY_DISTANCE
+}
+
+/// The transcript path prefix of a collection with a separate data directory, such as `/sermons`
+pub fn separate_collection_prefix(collection: &str) -> String {
+    format!("/{collection}")
+}
+
+fn read_stop_words(path: &Path) -> Option<Vec<String>> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    Some(contents.split_whitespace().map(normalize_word).collect())
+}
+
 fn get_project_dirs() -> Option<ProjectDirs> {
     ProjectDirs::from("com", APP_NAME, APP_NAME)
 }
@@ -98,6 +251,14 @@ fn get_stop_words_file_path() -> Option<PathBuf> {
     Some(get_config_path()?.mutated(|config| config.push("stop_words.txt")))
 }
 
+fn get_synonyms_file_path() -> Option<PathBuf> {
+    Some(get_config_path()?.mutated(|config| config.push("synonyms.txt")))
+}
+
+fn get_word_endings_file_path() -> Option<PathBuf> {
+    Some(get_config_path()?.mutated(|config| config.push("word_endings.txt")))
+}
+
 fn get_config_file_path() -> Option<PathBuf> {
     Some(get_config_path()?.mutated(|config| config.push("config.toml")))
 }
@@ -132,9 +293,15 @@ fn load_config() -> Result<AppConfig, Box<dyn std::error::Error>> {
         std::fs::write(&config_path, toml)?;
         // Try adding stop words
         if let Some(stop_words_path) = get_stop_words_file_path() {
+            _ = std::fs::write(&stop_words_path, include_str!("../config/stop_words.txt"));
+        }
+        if let Some(synonyms_path) = get_synonyms_file_path() {
+            _ = std::fs::write(&synonyms_path, include_str!("../config/synonyms.txt"));
+        }
+        if let Some(word_endings_path) = get_word_endings_file_path() {
             _ = std::fs::write(
-                &stop_words_path,
-                include_str!("/home/dgmastertemple/.config/heurisko/stop_words.txt"),
+                &word_endings_path,
+                include_str!("../config/word_endings.txt"),
             );
         }
 
@@ -257,7 +424,7 @@ impl Default for FilePaths {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct OverwritableParameter<T> {
     /// The parameter's actual value
     pub value: T,
@@ -266,14 +433,26 @@ pub struct OverwritableParameter<T> {
 }
 impl<T> OverwritableParameter<T> {
     fn create(value: T) -> Self {
+        Self::new(value, true)
+    }
+
+    pub fn new(value: T, overwritable: bool) -> Self {
         Self {
             value,
-            overwritable: true,
+            overwritable,
         }
     }
+
+    /// Rejects a request that gives its own value (named `name`) when this isn't overwritable
+    pub fn validate_overwrite(&self, name: &str, requested: &Option<T>) -> Result<(), String> {
+        if requested.is_some() && !self.overwritable {
+            return Err(format!("{name} can't be overwritten"));
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Parameters {
     /// Amount of results to display per paginated result
     pub page_size: OverwritableParameter<usize>,
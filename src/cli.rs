@@ -1,8 +1,12 @@
-use crate::searcher::Searcher;
+use crate::searcher::{QueryParams, Searcher, TranscriptFilter};
 use crate::utils::{prompt, Timer};
-use crate::CONFIG;
 
-pub fn command_cli() {
+pub fn command_cli(collection: Option<String>) {
+    let filter = TranscriptFilter::collection(collection);
+    if let Err(err) = filter.validate() {
+        println!("{err}");
+        return;
+    }
     let mut timer = Timer::new();
     let searcher = Searcher::load();
     timer.print(
@@ -21,9 +25,18 @@ pub fn command_cli() {
             break;
         }
         timer.reset();
-        let results = searcher.search(input, CONFIG.context_size(), 0, true);
+        let params = QueryParams {
+            query: input,
+            filter: filter.clone(),
+            ..Default::default()
+        };
+        if let Err(err) = params.validate() {
+            println!("{err}");
+            continue;
+        }
+        let results = searcher.search(&params);
         timer.print(format!("Query Complete").as_str());
-        for result in results {
+        for result in results.results {
             let start = result.words.iter().find_map(|w| w.start).unwrap_or(0.0);
             let end = result
                 .words
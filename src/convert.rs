@@ -1,17 +1,23 @@
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
 
 use walkdir::WalkDir;
 
 use crate::{
     app_config::APP_EXT,
-    hsk_file::{HskFile, HskResult},
+    hsk_file::{claim_transcript_id, HskFile, HskResult, TranscriptMetadata},
+    searcher::is_transcript_file,
     CONFIG,
 };
 
+/// - `metadata`: given to every converted transcript (otherwise the metadata of any transcript being
+///   replaced is kept)
 pub fn command_convert(
     source: String,
     destination: Option<String>,
     flatten: bool,
+    metadata: Option<TranscriptMetadata>,
 ) -> HskResult<()> {
     let mut data_dir = CONFIG.data_dir();
     let source = Path::new(&source);
@@ -24,7 +30,7 @@ pub fn command_convert(
         let mut dest = data_dir.join(source.file_name().unwrap());
         dest.set_extension(APP_EXT);
         println!("Converting: {source:?} -> {dest:?}\n");
-        HskFile::convert(source, dest.as_path())?;
+        HskFile::convert(source, dest.as_path(), metadata.as_ref())?;
     }
     if source.is_dir() {
         println!("Directory:");
@@ -40,9 +46,167 @@ pub fn command_convert(
                 }
                 dest.set_extension(APP_EXT);
                 println!("Converting: {path:?} -> {dest:?}\n");
-                HskFile::convert(path, dest.as_path())?;
+                HskFile::convert(path, dest.as_path(), metadata.as_ref())?;
             }
         }
     }
     Ok(())
 }
+
+/// Reconvert every `.hsk` file whose provenance says it is stale (or all of them with `all`)
+pub fn command_reconvert(all: bool, dry_run: bool) -> HskResult<()> {
+    let data_dir = CONFIG.data_dir();
+    for entry in WalkDir::new(&data_dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !is_transcript_file(path) {
+            continue;
+        }
+        let Some(provenance) = HskFile::read(path)?.provenance else {
+            println!("Skipping (no provenance recorded): {path:?}\n");
+            continue;
+        };
+        let stale_reasons = provenance.stale_reasons();
+        if stale_reasons.is_empty() && !all {
+            continue;
+        }
+        println!("Stale: {path:?} ({})", stale_reasons.join(", "));
+        let Some(source_path) = provenance.source_path.filter(|source| source.is_file()) else {
+            println!("Skipping (source is missing)\n");
+            continue;
+        };
+        if !dry_run {
+            println!("Converting: {source_path:?} -> {path:?}");
+            HskFile::convert(&source_path, path, None)?;
+        }
+        println!();
+    }
+    Ok(())
+}
+
+/**
+Save an id to every transcript without one of its own (such as ones converted before ids were
+saved, or copies of another transcript), which otherwise get a new one each time they are loaded
+- As when loading, transcripts that already have an id keep it, unless an earlier one has it too
+*/
+pub fn command_migrate(dry_run: bool) -> HskResult<()> {
+    let mut paths = vec![];
+    for (_, root) in CONFIG.transcript_roots() {
+        let walker = WalkDir::new(root).sort_by_file_name();
+        paths.extend(
+            walker
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .map(|entry| entry.into_path())
+                .filter(|path| is_transcript_file(path)),
+        );
+    }
+    // a separate collection directory nested in another root is walked twice
+    let mut seen = BTreeSet::new();
+    paths.retain(|path| seen.insert(path.clone()));
+
+    let files = paths
+        .into_iter()
+        .filter_map(|path| Some((HskFile::read(&path).ok()?, path)))
+        .collect::<Vec<_>>();
+    let mut taken = BTreeSet::new();
+    let without_id = files
+        .into_iter()
+        .filter(|(file, _)| !file.id.is_some_and(|id| taken.insert(id)))
+        .collect::<Vec<_>>();
+    let mut next_id = taken.last().map_or(0, |id| id + 1);
+    for (mut file, path) in without_id {
+        if dry_run {
+            println!("Needs an id: {path:?}");
+            continue;
+        }
+        let id = claim_transcript_id(next_id)?;
+        next_id = id + 1;
+        println!("Id {id}: {path:?}");
+        file.id = Some(id);
+        file.save(&path)?;
+    }
+    Ok(())
+}
+
+/// The `.hsk` file that a transcript (identified by its path relative to the data directory) is stored at
+pub fn transcript_file_path(transcript: &str) -> HskResult<PathBuf> {
+    // transcripts in a collection with a separate data directory are prefixed by `/` and its name
+    let (prefix, root) = CONFIG
+        .transcript_roots()
+        .into_iter()
+        .rev()
+        .find(|(prefix, _)| Path::new(transcript).starts_with(prefix))
+        .expect("The data directory has an empty prefix");
+    let relative_path = Path::new(transcript).strip_prefix(&prefix)?;
+    let stays_in_data_dir = relative_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if relative_path.as_os_str().is_empty() || !stays_in_data_dir {
+        return Err(format!("Invalid transcript path {transcript:?}").into());
+    }
+    let mut path = root.join(relative_path);
+    path.set_extension(APP_EXT);
+    Ok(path)
+}
+
+/**
+Convert the raw contents of any supported transcript format and save it in the data directory
+- `replace`: whether the transcript must already exist (or must not exist yet)
+- `metadata`: replaces the metadata of the transcript being replaced, which is kept otherwise
+*/
+pub fn save_transcript(
+    transcript: &str,
+    contents: &str,
+    replace: bool,
+    metadata: Option<TranscriptMetadata>,
+) -> HskResult<PathBuf> {
+    let path = transcript_file_path(transcript)?;
+    if replace && !path.is_file() {
+        return Err(format!("Transcript {transcript:?} does not exist").into());
+    }
+    let mut hsk = HskFile::infer_contents(contents, None)?;
+    if replace {
+        hsk.keep_existing(&path)?;
+    } else {
+        hsk.id = Some(claim_transcript_id(0)?);
+    }
+    if let Some(metadata) = metadata {
+        metadata.validate()?;
+        hsk.metadata = metadata;
+    }
+    if replace {
+        hsk.save(&path)?;
+    } else {
+        hsk.save_new(&path)
+            .map_err(|err| match err.downcast_ref::<std::io::Error>() {
+                Some(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    format!("Transcript {transcript:?} already exists").into()
+                }
+                _ => err,
+            })?;
+    }
+    Ok(path)
+}
+
+/// Replace the metadata of a transcript that is already saved in the data directory
+pub fn save_transcript_metadata(
+    transcript: &str,
+    metadata: TranscriptMetadata,
+) -> HskResult<PathBuf> {
+    metadata.validate()?;
+    let path = transcript_file_path(transcript)?;
+    let mut hsk =
+        HskFile::read(&path).map_err(|_| format!("Transcript {transcript:?} does not exist"))?;
+    hsk.metadata = metadata;
+    hsk.save(&path)?;
+    Ok(path)
+}
+
+pub fn delete_transcript(transcript: &str) -> HskResult<PathBuf> {
+    let path = transcript_file_path(transcript)?;
+    if !path.is_file() {
+        return Err(format!("Transcript {transcript:?} does not exist").into());
+    }
+    std::fs::remove_file(&path)?;
+    Ok(path)
+}
@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use rocket::FromFormField;
+use serde::{Deserialize, Serialize};
+
+/// Which query words also match the indexed words that are spelled similarly
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, FromFormField, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FuzzyMode {
+    #[default]
+    Off,
+    /// Only words that aren't indexed (such as a misspelled "Nebuchadnezar")
+    Unmatched,
+    All,
+}
+
+/**
+How many letters can differ for a word to match `word` fuzzily
+- Short words allow fewer (none below 4 letters), since almost every short word is close to another
+*/
+pub fn allowed_distance(word: &str, max_distance: usize) -> usize {
+    let length = word.chars().count();
+    let distance = match length {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    distance.min(max_distance)
+}
+
+/// The fewest letters that have to be inserted, removed or replaced to turn `a` into `b`
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/**
+A BK-tree of words, for finding every word within an edit distance of another without comparing it to
+all of them
+- Each child is keyed by its distance from its parent, so (by the triangle inequality) only children
+  whose key is within the allowed distance of the parent's distance can have matches
+- Removed words stay in the tree (so that their children can still be reached) until more than half
+  of it is removed, when it is built again from the words that are left
+- Copies share the nodes they have in common, like a [`SharedMap`](crate::searcher::SharedMap)
+*/
+#[derive(Clone, Debug, Default)]
+pub struct BkTree {
+    nodes: im::Vector<BkNode>,
+    removed: usize,
+}
+
+#[derive(Clone, Debug)]
+struct BkNode {
+    word: Arc<str>,
+    removed: bool,
+    /// (distance from this word, node index)
+    children: Vec<(usize, usize)>,
+}
+
+impl BkTree {
+    pub fn new(words: impl IntoIterator<Item = impl Into<Arc<str>>>) -> Self {
+        let mut tree = Self::default();
+        for word in words {
+            tree.insert(word);
+        }
+        tree
+    }
+
+    pub fn insert(&mut self, word: impl Into<Arc<str>>) {
+        let word = word.into();
+        let new_index = self.nodes.len();
+        if new_index > 0 {
+            let mut index = 0;
+            loop {
+                let distance = levenshtein(&self.nodes[index].word, &word);
+                if distance == 0 {
+                    if self.nodes[index].removed {
+                        self.nodes[index].removed = false;
+                        self.removed -= 1;
+                    }
+                    return;
+                }
+                let child = self.nodes[index]
+                    .children
+                    .iter()
+                    .find(|(child_distance, _)| *child_distance == distance);
+                match child {
+                    Some((_, child)) => index = *child,
+                    None => {
+                        self.nodes[index].children.push((distance, new_index));
+                        break;
+                    }
+                }
+            }
+        }
+        self.nodes.push_back(BkNode {
+            word,
+            removed: false,
+            children: vec![],
+        });
+    }
+
+    pub fn remove(&mut self, word: &str) {
+        let mut index = 0;
+        while let Some(node) = self.nodes.get(index) {
+            let distance = levenshtein(&node.word, word);
+            if distance == 0 {
+                if !node.removed {
+                    self.nodes[index].removed = true;
+                    self.removed += 1;
+                }
+                break;
+            }
+            let Some((_, child)) = node
+                .children
+                .iter()
+                .find(|(child_distance, _)| *child_distance == distance)
+            else {
+                return;
+            };
+            index = *child;
+        }
+        if self.removed * 2 > self.nodes.len() {
+            let words: Vec<Arc<str>> = self
+                .nodes
+                .iter()
+                .filter(|node| !node.removed)
+                .map(|node| node.word.clone())
+                .collect();
+            *self = Self::new(words);
+        }
+    }
+
+    /// Every word within `max_distance` of `word` (including itself), closest first
+    pub fn find(&self, word: &str, max_distance: usize) -> Vec<(&str, usize)> {
+        let mut found = vec![];
+        let mut to_visit = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(index) = to_visit.pop() {
+            let node = &self.nodes[index];
+            let distance = levenshtein(&node.word, word);
+            if distance <= max_distance && !node.removed {
+                found.push((&*node.word, distance));
+            }
+            to_visit.extend(
+                node.children
+                    .iter()
+                    .filter(|(child_distance, _)| child_distance.abs_diff(distance) <= max_distance)
+                    .map(|(_, child)| *child),
+            );
+        }
+        found.sort_by(|(a_word, a_distance), (b_word, b_distance)| {
+            a_distance.cmp(b_distance).then_with(|| a_word.cmp(b_word))
+        });
+        found
+    }
+
+    /// Roughly how many bytes the words and the tree take up
+    pub fn heap_size(&self) -> usize {
+        self.nodes.len() * std::mem::size_of::<BkNode>()
+            + self
+                .nodes
+                .iter()
+                .map(|node| {
+                    node.word.len()
+                        + node.children.capacity() * std::mem::size_of::<(usize, usize)>()
+                })
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORDS: [&str; 10] = [
+        "nebuchadnezzar",
+        "nebuchadrezzar",
+        "grace",
+        "grade",
+        "graces",
+        "race",
+        "faith",
+        "faithful",
+        "love",
+        "loved",
+    ];
+
+    fn tree() -> BkTree {
+        BkTree::new(WORDS.iter().map(|word| word.to_string()))
+    }
+
+    #[test]
+    fn levenshtein_distances() {
+        assert_eq!(levenshtein("grace", "grace"), 0);
+        assert_eq!(levenshtein("grace", "grade"), 1);
+        assert_eq!(levenshtein("grace", "race"), 1);
+        assert_eq!(levenshtein("grace", "graces"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "love"), 4);
+    }
+
+    #[test]
+    fn short_words_allow_fewer_differences() {
+        assert_eq!(allowed_distance("joy", 2), 0);
+        assert_eq!(allowed_distance("grace", 2), 1);
+        assert_eq!(allowed_distance("nebuchadnezar", 2), 2);
+        assert_eq!(allowed_distance("nebuchadnezar", 1), 1);
+    }
+
+    #[test]
+    fn finds_every_word_within_the_distance() {
+        let tree = tree();
+        for word in ["grace", "nebuchadnezar", "faith", "lve", "zzz"] {
+            for max_distance in 0..=3 {
+                let mut expected: Vec<(&str, usize)> = WORDS
+                    .iter()
+                    .map(|other| (*other, levenshtein(other, word)))
+                    .filter(|(_, distance)| *distance <= max_distance)
+                    .collect();
+                expected.sort_by(|(a, a_distance), (b, b_distance)| {
+                    a_distance.cmp(b_distance).then_with(|| a.cmp(b))
+                });
+                assert_eq!(
+                    tree.find(word, max_distance),
+                    expected,
+                    "{word} {max_distance}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn removed_words_are_not_found() {
+        let mut tree = tree();
+        tree.remove("grace");
+        tree.remove("nothing");
+        assert_eq!(
+            tree.find("grace", 1),
+            vec![("graces", 1), ("grade", 1), ("race", 1)]
+        );
+        tree.insert("grace");
+        assert_eq!(tree.find("grace", 0), vec![("grace", 0)]);
+    }
+
+    #[test]
+    fn removing_most_words_rebuilds_the_tree() {
+        let mut tree = tree();
+        for word in &WORDS[..6] {
+            tree.remove(word);
+        }
+        assert_eq!(tree.nodes.len(), 4);
+        assert_eq!(tree.removed, 0);
+        assert_eq!(tree.find("faith", 3), vec![("faith", 0), ("faithful", 3)]);
+    }
+
+    #[test]
+    fn duplicates_are_inserted_once() {
+        let mut tree = tree();
+        tree.insert("grace".to_string());
+        assert_eq!(tree.find("grace", 0), vec![("grace", 0)]);
+    }
+}
@@ -1,14 +1,22 @@
-use std::collections::BTreeMap;
+use std::path::Path;
 
-use rocket::form::validate::Len;
+use rocket::data::{Limits, ToByteUnit};
 use rocket::response::status::BadRequest;
 use rocket::tokio::runtime::Runtime;
-use rocket::{get, post, routes};
-use serde::{Deserialize, Serialize};
+use rocket::tokio::task::spawn_blocking;
+use rocket::{delete, get, post, put, routes};
+use serde::Serialize;
 
 use crate::app_config::APP_DISPLAY_NAME;
-use crate::hsk_file::HskResult;
+use crate::convert::{delete_transcript, save_transcript, save_transcript_metadata};
+use crate::fuzzy::FuzzyMode;
+use crate::hsk_file::{HskResult, TranscriptMetadata};
+use crate::searcher::{
+    transcript_path, Map, QueryParams, Searcher, TranscriptFilter, TranscriptId,
+};
+use crate::stats::DEFAULT_TOP_WORDS;
 use crate::utils::Timer;
+use crate::watch::{reload_all, reload_own_changes, watch_data_dir};
 use crate::{CONFIG, SEARCHER};
 
 use rocket::fairing::{Fairing, Info, Kind};
@@ -30,7 +38,7 @@ impl Fairing for CORS {
         response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
         response.set_header(Header::new(
             "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, OPTIONS",
+            "POST, GET, PATCH, PUT, DELETE, OPTIONS",
         ));
         response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
         response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
@@ -43,7 +51,7 @@ pub fn command_host(port: u16) -> HskResult<()> {
     // force it to load when starting
     {
         let mut timer = Timer::new();
-        let searcher = SEARCHER.as_ref();
+        let searcher = SEARCHER.load();
         timer.print(
             format!(
                 "Searcher loaded {} transcripts",
@@ -52,22 +60,38 @@ pub fn command_host(port: u16) -> HskResult<()> {
             .as_str(),
         );
     }
+    // keep the index up to date with the data directory while hosting
+    let _watcher = watch_data_dir()?;
 
     // Launch rocket in the runtime
     _ = rt.block_on(async {
         let rocket = rocket::build()
-            .configure(rocket::Config::figment().merge(("port", port)))
+            .configure(
+                rocket::Config::figment()
+                    .merge(("port", port))
+                    // uploaded transcripts are far bigger than the default limit
+                    .merge(("limits", Limits::default().limit("string", 64.mebibytes()))),
+            )
             .attach(CORS)
             .mount(
                 "/",
                 routes![
                     index,
                     search,
+                    search_json,
                     search_exact,
                     ids,
+                    collections,
+                    provenance,
+                    stats,
                     diagnostics,
                     transcript,
-                    convert
+                    upload_transcript,
+                    replace_transcript,
+                    remove_transcript,
+                    metadata,
+                    replace_metadata,
+                    reload
                 ],
             );
         rocket
@@ -80,66 +104,184 @@ pub fn command_host(port: u16) -> HskResult<()> {
     Ok(())
 }
 
+/// Run the part of a handler that reads or writes files (or reindexes them) off of the async runtime
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, BadRequest<String>> {
+    spawn_blocking(work)
+        .await
+        .map_err(|err| BadRequest(err.to_string()))?
+        .map_err(BadRequest)
+}
+
 // Example route handlers
 #[get("/")]
 async fn index() -> &'static str {
     APP_DISPLAY_NAME
 }
 
-#[get("/ids")]
-async fn ids() -> String {
-    serde_json::to_string(
-        &SEARCHER
-            .transcript_paths
-            .iter()
-            .enumerate()
-            .map(|(id, path)| (id, path))
-            .collect::<BTreeMap<_, _>>(),
-    )
-    .expect("This can serialize")
+#[get("/ids?<filter..>")]
+async fn ids(filter: TranscriptFilter) -> Result<String, BadRequest<String>> {
+    filter.validate().map_err(BadRequest)?;
+    let searcher = SEARCHER.load();
+    let transcripts = searcher.filtered_transcripts(&filter);
+    let ids = searcher
+        .transcript_paths
+        .iter()
+        .filter(|(id, _)| transcripts.as_ref().is_none_or(|t| t.contains(id)))
+        .collect::<Map<_, _>>();
+    serde_json::to_string(&ids).map_err(|err| BadRequest(err.to_string()))
+}
+
+/// The names of every configured collection
+#[get("/collections")]
+async fn collections() -> Result<String, BadRequest<String>> {
+    serde_json::to_string(&CONFIG.collections.keys().collect::<Vec<_>>())
+        .map_err(|err| BadRequest(err.to_string()))
 }
 
-#[get("/search?<query>&<context>&<page>&<remove_stop_words>")]
+/// - Checking whether source files have changed reads them, so it is done off of the async runtime
+#[get("/provenance?<stale>")]
+async fn provenance(stale: Option<bool>) -> Result<String, BadRequest<String>> {
+    blocking(move || {
+        let listing = SEARCHER.load().provenance_listing(stale.unwrap_or(false));
+        serde_json::to_string(&listing).map_err(|err| err.to_string())
+    })
+    .await
+}
+
+/**
+What is in the index (see [`LibraryStats`](crate::stats::LibraryStats))
+- Counting every word of every transcript takes a while, so it is done off of the async runtime
+*/
+#[get("/stats?<top>&<filter..>")]
+async fn stats(top: Option<usize>, filter: TranscriptFilter) -> Result<String, BadRequest<String>> {
+    filter.validate().map_err(BadRequest)?;
+    blocking(move || {
+        let stats = SEARCHER
+            .load()
+            .stats(&filter, top.unwrap_or(DEFAULT_TOP_WORDS));
+        serde_json::to_string(&stats).map_err(|err| err.to_string())
+    })
+    .await
+}
+
+#[get(
+    "/search?<query>&<context>&<page>&<remove_stop_words>&<exclude_transcripts>&<stem>&<synonyms>&<phonetic>&<fuzzy>&<fuzzy_distance>&<window_seconds>&<context_seconds>&<reference>&<filter..>"
+)]
+#[allow(clippy::too_many_arguments)]
 async fn search(
-    query: String,
+    query: Option<String>,
     context: Option<usize>,
     page: Option<usize>,
-    remove_stop_words: bool,
+    remove_stop_words: Option<bool>,
+    exclude_transcripts: Option<bool>,
+    stem: Option<bool>,
+    synonyms: Option<bool>,
+    phonetic: Option<bool>,
+    fuzzy: Option<FuzzyMode>,
+    fuzzy_distance: Option<usize>,
+    window_seconds: Option<f64>,
+    context_seconds: Option<f64>,
+    reference: Option<String>,
+    filter: TranscriptFilter,
 ) -> Result<String, BadRequest<String>> {
-    let mut timer = Timer::new();
-    let page = page.unwrap_or(0);
-    let page_results = SEARCHER.search(
-        &query,
-        context.unwrap_or(CONFIG.context_size()),
-        page,
+    let params = QueryParams {
+        query: query.unwrap_or_default(),
+        page: page.unwrap_or(0),
+        context,
         remove_stop_words,
-    );
-    timer.print(format!("Searched {query:?}").as_str());
-    serde_json::to_string(&page_results).map_err(|err| BadRequest(err.to_string()))
+        exclude_transcripts: exclude_transcripts.unwrap_or(false),
+        stem: stem.unwrap_or(false),
+        synonyms: synonyms.unwrap_or(false),
+        phonetic: phonetic.unwrap_or(false),
+        fuzzy: fuzzy.unwrap_or_default(),
+        fuzzy_distance,
+        window_seconds,
+        context_seconds,
+        reference,
+        filter,
+        ..Default::default()
+    };
+    search_with(params).await
+}
+
+/// [`search`], but with every [`QueryParams`] setting given as the JSON body
+#[post("/search", data = "<data>")]
+async fn search_json(data: String) -> Result<String, BadRequest<String>> {
+    let params: QueryParams =
+        serde_json::from_str(&data).map_err(|err| BadRequest(err.to_string()))?;
+    search_with(params).await
 }
 
-#[get("/search_exact?<query>&<page>")]
-async fn search_exact(query: String, page: Option<usize>) -> Result<String, BadRequest<String>> {
+/// - Searches spread the transcripts over threads and wait for them, so they run off of the async
+///   runtime
+async fn search_with(params: QueryParams) -> Result<String, BadRequest<String>> {
+    params.validate().map_err(BadRequest)?;
+    blocking(move || {
+        let mut timer = Timer::new();
+        let page_results = SEARCHER.load().search(&params);
+        timer.print(format!("Searched {:?}", params.query).as_str());
+        serde_json::to_string(&page_results).map_err(|err| err.to_string())
+    })
+    .await
+}
+
+#[get("/search_exact?<query>&<page>&<filter..>")]
+async fn search_exact(
+    query: String,
+    page: Option<usize>,
+    filter: TranscriptFilter,
+) -> Result<String, BadRequest<String>> {
+    filter.validate().map_err(BadRequest)?;
     let mut timer = Timer::new();
     let page = page.unwrap_or(0);
-    let page_results = SEARCHER.search_exact(&query, page);
+    let page_results = SEARCHER.load().search_exact(&query, page, &filter);
     timer.print(format!("Searched {query:?}").as_str());
     serde_json::to_string(&page_results).map_err(|err| BadRequest(err.to_string()))
 }
 
-#[get("/diagnostics?<query>")]
-async fn diagnostics(query: String) -> Result<String, BadRequest<String>> {
-    serde_json::to_string(&SEARCHER.diagnose_query(query))
+#[get("/diagnostics?<query>&<collection>")]
+async fn diagnostics(
+    query: String,
+    collection: Option<String>,
+) -> Result<String, BadRequest<String>> {
+    TranscriptFilter::collection(collection.clone())
+        .validate()
+        .map_err(BadRequest)?;
+    serde_json::to_string(&SEARCHER.load().diagnose_query(query, collection.as_deref()))
         .map_err(|err| BadRequest(err.to_string()))
 }
 
-#[get("/transcript?<path>&<start>&<end>")]
+/// The path of the transcript identified by either its `path` or its `id` (as listed in `/ids`)
+fn resolve_transcript_path(
+    path: Option<String>,
+    id: Option<TranscriptId>,
+) -> Result<String, BadRequest<String>> {
+    match (path, id) {
+        (Some(path), _) => Ok(path),
+        (None, Some(id)) => SEARCHER
+            .load()
+            .transcript_paths
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| BadRequest(format!("No transcript has id {id}"))),
+        (None, None) => Err(BadRequest(String::from(
+            "Either `path` or `id` is required",
+        ))),
+    }
+}
+
+#[get("/transcript?<path>&<id>&<start>&<end>")]
 async fn transcript(
-    path: String,
+    path: Option<String>,
+    id: Option<TranscriptId>,
     start: Option<f64>,
     end: Option<f64>,
 ) -> Result<String, BadRequest<String>> {
+    let path = resolve_transcript_path(path, id)?;
     let words = &SEARCHER
+        .load()
         .get_transcript_words(path)
         .unwrap_or_else(|| vec![]);
     let words = {
@@ -162,17 +304,114 @@ async fn transcript(
     serde_json::to_string(words).map_err(|err| BadRequest(err.to_string()))
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct ConversionParameters {
-    source: String,
-    destination: Option<String>,
-    flatten: bool,
+/// The id and path of the transcript stored at `file`, as it is listed in `/ids`
+fn transcript_listing(searcher: &Searcher, file: &Path) -> Map<TranscriptId, String> {
+    transcript_path(&CONFIG.transcript_roots(), file)
+        .and_then(|path| Some((searcher.get_transcript_id(&path)?, path)))
+        .into_iter()
+        .collect()
+}
+
+/// Convert and save a new transcript (in any supported format) to `path`, and index it
+/// - Its metadata can be given with `date`, `speaker`, `series` and `tags`
+#[post("/transcript?<path>&<metadata..>", data = "<data>")]
+async fn upload_transcript(
+    path: String,
+    metadata: TranscriptMetadata,
+    data: String,
+) -> Result<String, BadRequest<String>> {
+    blocking(move || {
+        let file =
+            save_transcript(&path, &data, false, Some(metadata)).map_err(|err| err.to_string())?;
+        let searcher = reload_own_changes(std::slice::from_ref(&file));
+        serde_json::to_string(&transcript_listing(&searcher, &file)).map_err(|err| err.to_string())
+    })
+    .await
 }
 
-#[post("/convert", data = "<data>")]
-async fn convert(data: String) -> Result<String, BadRequest<String>> {
-    let data: ConversionParameters =
+/// Convert and save a transcript (in any supported format) over the existing one at `path` (or `id`),
+/// and reindex it
+/// - Its metadata is kept unless any of `date`, `speaker`, `series` or `tags` are given
+#[put("/transcript?<path>&<id>&<metadata..>", data = "<data>")]
+async fn replace_transcript(
+    path: Option<String>,
+    id: Option<TranscriptId>,
+    metadata: TranscriptMetadata,
+    data: String,
+) -> Result<String, BadRequest<String>> {
+    let path = resolve_transcript_path(path, id)?;
+    let metadata = (metadata != TranscriptMetadata::default()).then_some(metadata);
+    blocking(move || {
+        let file = save_transcript(&path, &data, true, metadata).map_err(|err| err.to_string())?;
+        let searcher = reload_own_changes(std::slice::from_ref(&file));
+        serde_json::to_string(&transcript_listing(&searcher, &file)).map_err(|err| err.to_string())
+    })
+    .await
+}
+
+/// Delete the transcript at `path` (or `id`) from disk and from the index
+#[delete("/transcript?<path>&<id>")]
+async fn remove_transcript(
+    path: Option<String>,
+    id: Option<TranscriptId>,
+) -> Result<String, BadRequest<String>> {
+    let path = resolve_transcript_path(path, id)?;
+    blocking(move || {
+        let file = delete_transcript(&path).map_err(|err| err.to_string())?;
+        let searcher = reload_own_changes(&[file]);
+        serde_json::to_string(&searcher.transcript_paths).map_err(|err| err.to_string())
+    })
+    .await
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TranscriptMetadataListing<'a> {
+    path: &'a String,
+    metadata: Option<&'a TranscriptMetadata>,
+}
+
+/// The metadata of every transcript that passes the filter
+#[get("/metadata?<filter..>")]
+async fn metadata(filter: TranscriptFilter) -> Result<String, BadRequest<String>> {
+    filter.validate().map_err(BadRequest)?;
+    let searcher = SEARCHER.load();
+    let transcripts = searcher.filtered_transcripts(&filter);
+    let listing = searcher
+        .transcript_paths
+        .iter()
+        .filter(|(id, _)| transcripts.as_ref().is_none_or(|t| t.contains(id)))
+        .map(|(id, path)| {
+            let metadata = searcher.transcript_metadata.get(id);
+            (id, TranscriptMetadataListing { path, metadata })
+        })
+        .collect::<Map<_, _>>();
+    serde_json::to_string(&listing).map_err(|err| BadRequest(err.to_string()))
+}
+
+/// Replace the metadata of the transcript at `path` (or `id`) with the JSON body
+#[put("/metadata?<path>&<id>", data = "<data>")]
+async fn replace_metadata(
+    path: Option<String>,
+    id: Option<TranscriptId>,
+    data: String,
+) -> Result<String, BadRequest<String>> {
+    let path = resolve_transcript_path(path, id)?;
+    let metadata: TranscriptMetadata =
         serde_json::from_str(&data).map_err(|err| BadRequest(err.to_string()))?;
-    dbg!(&data);
-    Ok(serde_json::to_string_pretty(&data).map_err(|err| BadRequest(err.to_string()))?)
+    blocking(move || {
+        let file = save_transcript_metadata(&path, metadata).map_err(|err| err.to_string())?;
+        let searcher = reload_own_changes(std::slice::from_ref(&file));
+        serde_json::to_string(&transcript_listing(&searcher, &file)).map_err(|err| err.to_string())
+    })
+    .await
+}
+
+/// Re-read every transcript in the data directory
+#[post("/reload")]
+async fn reload() -> Result<String, BadRequest<String>> {
+    blocking(|| {
+        let searcher = reload_all();
+        serde_json::to_string(&searcher.transcript_paths).map_err(|err| err.to_string())
+    })
+    .await
 }
@@ -1,8 +1,14 @@
+use cached::proc_macro::cached;
+use rocket::FromForm;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
 use std::error::Error;
-use std::fs::{File};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
 use zstd::stream::{read::Decoder, write::Encoder};
 
 use crate::input_files::sbv::SbvFile;
@@ -10,11 +16,54 @@ use crate::input_files::srt::SrtFile;
 use crate::input_files::whisper::UnalignedWhisperXFile;
 use crate::input_files::whisperx::WhisperXFile;
 use crate::input_files::youtube::YouTubeTranscriptFile;
-use crate::input_files::TranscriptFile;
-use crate::searcher::{normalize_word, Map};
+use crate::input_files::{TranscriptFile, TranscriptFormat};
+use crate::numbers::number_token;
+use crate::searcher::{is_transcript_file, normalize_word, Map, TranscriptId};
+use crate::CONFIG;
 
 pub type HskResult<T> = Result<T, Box<dyn Error>>;
 
+/// The file in the data directory that holds the next transcript id to give out
+const NEXT_ID_FILE: &str = ".next_transcript_id";
+
+/**
+A transcript id that no transcript has been given before, even one that has since been deleted
+- `at_least`: the lowest id that can be given (such as one past the highest id that is loaded)
+- The file holding the next id is locked while it is updated, so that the server and `convert`
+  don't give out the same id
+- Without that file, ids continue from the highest one saved in the data directories
+*/
+pub fn claim_transcript_id(at_least: TranscriptId) -> HskResult<TranscriptId> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(CONFIG.data_dir().join(NEXT_ID_FILE))?;
+    file.lock()?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let next_id = match contents.trim().parse::<TranscriptId>() {
+        Ok(next_id) => next_id,
+        Err(_) => highest_saved_id().map_or(0, |id| id + 1),
+    }
+    .max(at_least);
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    write!(file, "{}", next_id + 1)?;
+    Ok(next_id)
+}
+
+fn highest_saved_id() -> Option<TranscriptId> {
+    CONFIG
+        .transcript_roots()
+        .into_iter()
+        .flat_map(|(_, root)| WalkDir::new(root).into_iter().filter_map(|e| e.ok()))
+        .filter(|entry| is_transcript_file(entry.path()))
+        .filter_map(|entry| HskFile::read(entry.path()).ok()?.id)
+        .max()
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Word {
     pub word: String,
@@ -24,34 +73,104 @@ pub struct Word {
 
 pub type Words = Vec<Word>;
 
+type TranscriptParser = fn(&str) -> HskResult<HskFile>;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HskFile {
+    /// The transcript's persistent id, which moves with the file (see [`claim_transcript_id`])
+    #[serde(default)]
+    pub id: Option<TranscriptId>,
     pub words: Words,
     pub word_index_map: WordIndexMap,
+    /// Where this file came from, so stale or broken transcripts can be traced back and reconverted
+    /// - Files converted before this was recorded have none
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+    #[serde(default)]
+    pub metadata: TranscriptMetadata,
 }
 
 #[allow(unused)]
 impl HskFile {
-    pub fn convert(source: &Path, dest: &Path) -> HskResult<()> {
-        let hsk = HskFile::infer(&source)?;
+    /// - `metadata`: replaces the metadata of the transcript at `dest` (if there is one), which is kept otherwise
+    pub fn convert(
+        source: &Path,
+        dest: &Path,
+        metadata: Option<&TranscriptMetadata>,
+    ) -> HskResult<()> {
+        let mut hsk = HskFile::infer(&source)?;
+        hsk.keep_existing(dest)?;
+        if let Some(metadata) = metadata {
+            hsk.metadata = metadata.clone();
+        }
         hsk.save(dest)
     }
+
+    /**
+    Keep the id and metadata of the transcript already saved at `path` (if there is one), for when
+    it is replaced
+    - A new transcript (or one saved before it had an id) is given a new id
+    */
+    pub fn keep_existing(&mut self, path: &Path) -> HskResult<()> {
+        if let Ok(existing) = HskFile::read(path) {
+            self.id = existing.id;
+            self.metadata = existing.metadata;
+        }
+        if self.id.is_none() {
+            self.id = Some(claim_transcript_id(0)?);
+        }
+        Ok(())
+    }
     pub fn infer(path: &Path) -> HskResult<Self> {
-        let funcs = vec![
-            WhisperXFile::into_hsk,
-            UnalignedWhisperXFile::into_hsk,
-            YouTubeTranscriptFile::into_hsk,
-            SrtFile::into_hsk,
-            SbvFile::into_hsk,
+        let contents = std::fs::read_to_string(path)?;
+        let source_path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        Self::infer_contents(&contents, Some(source_path))
+            .map_err(|_| format!("Could not parse {:?} into any type", path).into())
+    }
+
+    /// Like [`HskFile::infer`], but for transcripts that aren't read from a file (such as uploads)
+    pub fn infer_contents(contents: &str, source_path: Option<PathBuf>) -> HskResult<Self> {
+        let funcs: Vec<(TranscriptFormat, TranscriptParser)> = vec![
+            (TranscriptFormat::WhisperX, WhisperXFile::into_hsk),
+            (
+                TranscriptFormat::UnalignedWhisperX,
+                UnalignedWhisperXFile::into_hsk,
+            ),
+            (TranscriptFormat::YouTube, YouTubeTranscriptFile::into_hsk),
+            (TranscriptFormat::Srt, SrtFile::into_hsk),
+            (TranscriptFormat::Sbv, SbvFile::into_hsk),
         ];
-        let found = funcs.iter().find_map(|func| func(path).ok());
-        found.ok_or(format!("Could not parse {:?} into any type", path).into())
+        let (format, mut hsk) = funcs
+            .into_iter()
+            .find_map(|(format, func)| Some((format, func(contents).ok()?)))
+            .ok_or("Could not parse transcript into any type")?;
+        hsk.provenance = Some(Provenance::new(source_path, contents.as_bytes(), format)?);
+        Ok(hsk)
     }
 
     pub fn from_words(words: Vec<Word>) -> Self {
         Self {
+            id: None,
             word_index_map: index_words(&words),
             words,
+            provenance: None,
+            metadata: TranscriptMetadata::default(),
+        }
+    }
+
+    /**
+    Rebuild [`HskFile::word_index_map`] if it wasn't made by the current [`index_tokens`], such as in
+    files converted before numbers were indexed as digits, so they can be searched the same way
+    without being reconverted first
+    - Files without provenance are older than [`TokenizerSettings`], so they are always rebuilt
+    */
+    pub fn reindex_if_stale(&mut self) {
+        let is_current = self
+            .provenance
+            .as_ref()
+            .is_some_and(|provenance| provenance.tokenizer == TokenizerSettings::current());
+        if !is_current {
+            self.word_index_map = index_words(&self.words);
         }
     }
 
@@ -60,6 +179,19 @@ impl HskFile {
         compress_and_write(data, path)
     }
 
+    /// Like [`HskFile::save`], but fails instead of replacing a file that is already at `path`
+    /// (even one created while this is being saved)
+    pub fn save_new(&self, path: &Path) -> HskResult<()> {
+        let data = serde_json::to_string(self)?.into_bytes();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+        compress_into(data, file).inspect_err(|_| {
+            _ = std::fs::remove_file(path);
+        })
+    }
+
     pub fn read(path: &Path) -> HskResult<Self> {
         let data = read_and_decompress(path)?;
         let value = serde_json::from_str(&String::from_utf8(data)?)?;
@@ -67,13 +199,183 @@ impl HskFile {
     }
 }
 
+/// Describes a transcript so searches can be narrowed down by it (see [`TranscriptFilter`](crate::searcher::TranscriptFilter))
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, FromForm)]
+pub struct TranscriptMetadata {
+    /// When it was recorded, as `YYYY-MM-DD` (or `YYYY-MM` or `YYYY` when that is all that is known)
+    pub date: Option<String>,
+    pub speaker: Option<String>,
+    pub series: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl TranscriptMetadata {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.date {
+            Some(date) if !is_valid_date(date) => Err(format!(
+                "Invalid date {date:?}, expected `YYYY-MM-DD`, `YYYY-MM` or `YYYY`"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The year of [`TranscriptMetadata::date`]
+    pub fn year(&self) -> Option<&str> {
+        self.date.as_deref().and_then(|date| date.get(0..4))
+    }
+}
+
+/// `YYYY-MM-DD`, `YYYY-MM` or `YYYY`
+pub fn is_valid_date(date: &str) -> bool {
+    let parts: Vec<&str> = date.split('-').collect();
+    let expected_lengths = [4, 2, 2];
+    parts.len() <= expected_lengths.len()
+        && parts
+            .iter()
+            .zip(expected_lengths)
+            .all(|(part, len)| part.len() == len && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/**
+Compare two valid dates (see [`is_valid_date`]) by year, then month, then day
+- Only the parts both have are compared, so `2023` is equal to `2023-01` and to `2023-05-14`, as a
+  date that only gives the year could be any day in it
+*/
+pub fn compare_dates(date: &str, other: &str) -> Ordering {
+    let parts = |date: &str| -> Vec<u32> {
+        date.split('-')
+            .map(|part| part.parse().unwrap_or_default())
+            .collect()
+    };
+    parts(date)
+        .iter()
+        .zip(parts(other).iter())
+        .map(|(part, other)| part.cmp(other))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Bump this whenever word splitting, [`normalize_word`] or [`index_tokens`] changes, so files indexed
+/// the old way are reported as stale
+pub const TOKENIZER_VERSION: u32 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenizerSettings {
+    pub version: u32,
+    /// How source text is split into [`Word`]s
+    pub split: String,
+    /// How each [`Word`] is normalized before being put in the [`WordIndexMap`]
+    pub normalization: String,
+}
+
+impl TokenizerSettings {
+    pub fn current() -> Self {
+        Self {
+            version: TOKENIZER_VERSION,
+            split: String::from("whitespace"),
+            normalization: String::from("alphanumeric, ascii lowercase, numbers as digits"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Absolute path of the file this was converted from
+    /// - [`None`] when it was uploaded through the API
+    pub source_path: Option<PathBuf>,
+    /// SHA-256 of the source file's contents at the time of conversion
+    pub source_hash: String,
+    /// The parser that [`HskFile::infer`] picked
+    pub format: TranscriptFormat,
+    /// The heurisko version that did the conversion
+    pub converter_version: String,
+    /// Seconds since the Unix epoch
+    pub converted_at: u64,
+    pub tokenizer: TokenizerSettings,
+}
+
+impl Provenance {
+    pub fn new(
+        source_path: Option<PathBuf>,
+        contents: &[u8],
+        format: TranscriptFormat,
+    ) -> HskResult<Self> {
+        Ok(Self {
+            source_path,
+            source_hash: hash_contents(contents),
+            format,
+            converter_version: String::from(env!("CARGO_PKG_VERSION")),
+            converted_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            tokenizer: TokenizerSettings::current(),
+        })
+    }
+
+    /**
+    Every reason this file should be reconverted (empty when it is up to date)
+    - This reads the source file to compare hashes, unless it hasn't changed since it was last hashed
+      (see [`hash_file`])
+    */
+    pub fn stale_reasons(&self) -> Vec<String> {
+        let mut reasons = vec![];
+        if self.converter_version != env!("CARGO_PKG_VERSION") {
+            reasons.push(format!(
+                "converted by heurisko {} (current is {})",
+                self.converter_version,
+                env!("CARGO_PKG_VERSION")
+            ));
+        }
+        if self.tokenizer != TokenizerSettings::current() {
+            reasons.push(format!(
+                "tokenized with version {} (current is {})",
+                self.tokenizer.version, TOKENIZER_VERSION
+            ));
+        }
+        if let Some(source_path) = &self.source_path {
+            match hash_file(source_path) {
+                Some(hash) if hash != self.source_hash => {
+                    reasons.push(String::from("source file has changed"))
+                }
+                Some(_) => {}
+                None => reasons.push(String::from("source file is missing")),
+            }
+        }
+        reasons
+    }
+}
+
+fn hash_contents(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+/// The hash of the file at `path` ([`None`] when it can't be read), which is only read again once its
+/// modification time or size changes
+fn hash_file(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    hash_file_version(
+        path.to_path_buf(),
+        metadata.modified().ok()?,
+        metadata.len(),
+    )
+}
+
+/// - The modification time and size are only there to be part of the cache key
+#[cached(size = 65536, option = true)]
+fn hash_file_version(path: PathBuf, _modified: SystemTime, _len: u64) -> Option<String> {
+    let contents = std::fs::read(path).ok()?;
+    Some(hash_contents(&contents))
+}
+
 const COMPRESSION_LEVEL: i32 = 3;
 
 fn compress_and_write(data: Vec<u8>, path: &Path) -> HskResult<()> {
     if let Some(parent) = path.parent() {
         std::fs::create_dir_all(parent)?;
     }
-    let file = File::create(path)?;
+    compress_into(data, File::create(path)?)
+}
+
+fn compress_into(data: Vec<u8>, file: File) -> HskResult<()> {
     let mut encoder = Encoder::new(file, COMPRESSION_LEVEL)?;
     encoder.write_all(&data)?;
     encoder.finish()?;
@@ -89,12 +391,34 @@ fn read_and_decompress(path: &Path) -> HskResult<Vec<u8>> {
 }
 
 pub type WordIndexMap = Map<String, Vec<usize>>;
+
+/**
+Every token that each word is indexed under, as (word index, token)
+- Its normalized form (see [`normalize_word`]), and for numbers the digits they stand for (see
+  [`number_token`]), both of the number it starts (such as "21" for the "twenty" of "twenty one") and
+  of the word on its own (such as "20"), so that "twelve" is found by searching for "12"
+*/
+pub fn index_tokens<'a>(words: impl IntoIterator<Item = &'a str>) -> Vec<(usize, String)> {
+    let words: Vec<String> = words.into_iter().map(normalize_word).collect();
+    let mut tokens = vec![];
+    for (idx, word) in words.iter().enumerate() {
+        let mut word_tokens = vec![word.clone()];
+        let numbers = [
+            number_token(&words[idx..]),
+            number_token(std::slice::from_ref(word)),
+        ];
+        for (number, _) in numbers.into_iter().flatten() {
+            if !word_tokens.contains(&number) {
+                word_tokens.push(number);
+            }
+        }
+        tokens.extend(word_tokens.into_iter().map(|token| (idx, token)));
+    }
+    tokens
+}
+
 fn index_words(words: &Vec<Word>) -> WordIndexMap {
-    let index_word_pairs = words
-        .iter()
-        .enumerate()
-        .map(|(idx, word)| (idx, normalize_word(&word.word)))
-        .collect::<Vec<_>>();
+    let index_word_pairs = index_tokens(words.iter().map(|word| word.word.as_str()));
 
     let mut word_index_map = WordIndexMap::default();
     for (idx, word) in index_word_pairs {
@@ -1,5 +1,7 @@
 use std::path::Path;
 
+use serde::{Deserialize, Serialize};
+
 use crate::hsk_file::{HskFile, HskResult, Words};
 
 pub mod sbv;
@@ -9,9 +11,23 @@ pub mod whisperx;
 pub mod youtube;
 
 pub trait TranscriptFile: Sized {
-    fn read(path: &Path) -> HskResult<Self>;
+    fn parse(contents: &str) -> HskResult<Self>;
     fn into_words(self) -> HskResult<Words>;
-    fn into_hsk(path: &Path) -> HskResult<HskFile> {
-        Ok(HskFile::from_words(Self::read(path)?.into_words()?))
+    fn read(path: &Path) -> HskResult<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+    fn into_hsk(contents: &str) -> HskResult<HskFile> {
+        Ok(HskFile::from_words(Self::parse(contents)?.into_words()?))
     }
 }
+
+/// Which parser [`HskFile::infer`] used to read a source transcript
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscriptFormat {
+    WhisperX,
+    UnalignedWhisperX,
+    YouTube,
+    Srt,
+    Sbv,
+}
@@ -1,4 +1,4 @@
-use std::{fmt::Display, path::Path};
+use std::fmt::Display;
 
 use cached::proc_macro::cached;
 use regex::Regex;
@@ -50,10 +50,9 @@ pub struct SbvFile {
 }
 
 impl TranscriptFile for SbvFile {
-    fn read(path: &Path) -> HskResult<Self> {
-        let contents = std::fs::read_to_string(path)?;
+    fn parse(contents: &str) -> HskResult<Self> {
         let mut segments = vec![];
-        for cap in sbv_regex().captures_iter(&contents) {
+        for cap in sbv_regex().captures_iter(contents) {
             segments.push(SbvSegment {
                 start: SbvTime {
                     hours: cap.get(1).unwrap().as_str().parse()?,
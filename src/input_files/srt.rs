@@ -1,4 +1,4 @@
-use std::{fmt::Display, path::Path};
+use std::fmt::Display;
 
 use cached::proc_macro::cached;
 use regex::Regex;
@@ -51,10 +51,9 @@ pub struct SrtFile {
 }
 
 impl TranscriptFile for SrtFile {
-    fn read(path: &Path) -> HskResult<Self> {
-        let contents = std::fs::read_to_string(path)?;
+    fn parse(contents: &str) -> HskResult<Self> {
         let mut segments = vec![];
-        for cap in srt_regex().captures_iter(&contents) {
+        for cap in srt_regex().captures_iter(contents) {
             segments.push(SrtSegment {
                 id: cap.get(1).unwrap().as_str().parse()?,
                 start: SrtTime {
@@ -1,5 +1,3 @@
-use std::path::Path;
-
 use serde::{Deserialize, Serialize};
 
 use crate::hsk_file::Word;
@@ -32,8 +30,8 @@ pub struct WhisperXWord {
 }
 
 impl TranscriptFile for WhisperXFile {
-    fn read(path: &Path) -> crate::hsk_file::HskResult<Self> {
-        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    fn parse(contents: &str) -> crate::hsk_file::HskResult<Self> {
+        Ok(serde_json::from_str(contents)?)
     }
 
     fn into_words(self) -> crate::hsk_file::HskResult<crate::hsk_file::Words> {
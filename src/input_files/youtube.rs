@@ -27,8 +27,8 @@ pub struct YouTubeTranscriptSegment {
 }
 
 impl TranscriptFile for YouTubeTranscriptFile {
-    fn read(path: &std::path::Path) -> crate::hsk_file::HskResult<Self> {
-        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    fn parse(contents: &str) -> crate::hsk_file::HskResult<Self> {
+        Ok(serde_json::from_str(contents)?)
     }
 
     fn into_words(self) -> crate::hsk_file::HskResult<crate::hsk_file::Words> {
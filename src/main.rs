@@ -1,29 +1,46 @@
 pub mod app_config;
 pub mod cli;
 pub mod convert;
+pub mod fuzzy;
 pub mod host;
 pub mod hsk_file;
 pub mod input_files;
 pub mod merge;
+pub mod numbers;
+pub mod phonetic;
+pub mod postings;
+pub mod query;
+pub mod relevance;
+pub mod scripture;
 pub mod searcher;
+pub mod stats;
+pub mod stemmer;
+pub mod synonyms;
 pub mod utils;
+pub mod vocabulary;
+pub mod watch;
 pub mod word_id;
 
 use std::sync::Arc;
 
 use crate::searcher::Searcher;
 use app_config::AppConfig;
+use arc_swap::ArcSwap;
 use clap::{Args, Parser, Subcommand};
 use cli::command_cli;
-use convert::command_convert;
+use convert::{command_convert, command_migrate, command_reconvert};
 use host::command_host;
+use hsk_file::TranscriptMetadata;
 use once_cell::sync::Lazy;
+use stats::{command_stats, DEFAULT_TOP_WORDS};
 
 pub static CONFIG: Lazy<Arc<AppConfig>> = Lazy::new(|| {
     Arc::new(AppConfig::load().expect("Failed to load config + Failed to create default config"))
 });
 
-pub static SEARCHER: Lazy<Arc<Searcher>> = Lazy::new(|| Arc::new(Searcher::load()));
+/// Swapped out for a new [`Searcher`] whenever transcripts change (see [`watch`])
+pub static SEARCHER: Lazy<ArcSwap<Searcher>> =
+    Lazy::new(|| ArcSwap::from_pointee(Searcher::load()));
 
 #[derive(Debug, Parser)]
 #[command(author = "Blake Scampone", version = "1.0", about = "heuriskó")]
@@ -43,6 +60,59 @@ pub struct CommandConvert {
     /// Whether or not to preserve source folder directory hierarchies
     #[arg(short, long)]
     flatten: bool,
+    /// When the transcripts were recorded (`YYYY-MM-DD`, `YYYY-MM` or `YYYY`)
+    #[arg(long)]
+    date: Option<String>,
+    /// Who is speaking in the transcripts
+    #[arg(long)]
+    speaker: Option<String>,
+    /// The series the transcripts are a part of
+    #[arg(long)]
+    series: Option<String>,
+    /// A tag to give the transcripts (can be repeated)
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+}
+
+/// Reconvert transcripts from their recorded source files
+#[derive(Debug, Args)]
+pub struct CommandReconvert {
+    /// Reconvert every transcript with a recorded source, not just the stale ones
+    #[arg(short, long)]
+    all: bool,
+    /// Only list the transcripts that would be reconverted
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+}
+
+/// Save ids to the transcripts that don't have one of their own
+#[derive(Debug, Args)]
+pub struct CommandMigrate {
+    /// Only list the transcripts that would be given an id
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+}
+
+/// Search interactively
+#[derive(Debug, Args)]
+pub struct CommandCli {
+    /// Only search the transcripts in this collection
+    #[arg(short, long)]
+    collection: Option<String>,
+}
+
+/// Show what is in the index
+#[derive(Debug, Args)]
+pub struct CommandStats {
+    /// Only count the transcripts in this collection
+    #[arg(short, long)]
+    collection: Option<String>,
+    /// How many of the most common words to list
+    #[arg(short, long, default_value_t = DEFAULT_TOP_WORDS)]
+    top: usize,
+    /// Print the same JSON that `/stats` returns
+    #[arg(long)]
+    json: bool,
 }
 
 #[derive(Debug, Args)]
@@ -56,8 +126,17 @@ enum Commands {
     /// Convert files or directories
     Convert(CommandConvert),
 
+    /// Reconvert stale transcripts from their recorded source files
+    Reconvert(CommandReconvert),
+
+    /// Save ids to the transcripts that don't have one of their own
+    Migrate(CommandMigrate),
+
     /// Run the interactive CLI
-    Cli,
+    Cli(CommandCli),
+
+    /// Show statistics about the index
+    Stats(CommandStats),
 
     /// Host the web server
     Host(CommandHost),
@@ -74,9 +153,33 @@ pub fn parse_cli() -> Result<(), Box<dyn std::error::Error>> {
             source,
             flatten,
             destination,
-        }) => command_convert(source, destination, flatten)?,
-
-        Commands::Cli => command_cli(),
+            date,
+            speaker,
+            series,
+            tags,
+        }) => {
+            let metadata = TranscriptMetadata {
+                date,
+                speaker,
+                series,
+                tags,
+            };
+            metadata.validate()?;
+            let metadata = (metadata != TranscriptMetadata::default()).then_some(metadata);
+            command_convert(source, destination, flatten, metadata)?
+        }
+
+        Commands::Reconvert(CommandReconvert { all, dry_run }) => command_reconvert(all, dry_run)?,
+
+        Commands::Migrate(CommandMigrate { dry_run }) => command_migrate(dry_run)?,
+
+        Commands::Cli(CommandCli { collection }) => command_cli(collection),
+
+        Commands::Stats(CommandStats {
+            collection,
+            top,
+            json,
+        }) => command_stats(collection, top, json)?,
 
         Commands::Host(CommandHost { port }) => command_host(port.unwrap_or(8000))?,
     };
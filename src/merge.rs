@@ -1,49 +1,104 @@
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::vocabulary::IndexedWord;
 use crate::word_id::{WordId, WordIdSet};
 use std::cmp::Ordering;
 use std::cmp::Reverse;
-use std::collections::BTreeMap;
 use std::collections::BinaryHeap;
-use std::collections::VecDeque;
 
-#[derive(Debug, Eq)]
-struct Item<'a> {
-    arr: &'a Vec<usize>,
-    idx: usize,
+/// How far apart word indices can be to still be in the same range
+pub trait AllowedRange {
+    /// How the distance from `from` to `to` (where `from <= to`) compares to the furthest that a
+    /// range can stretch
+    fn compare(&self, from: usize, to: usize) -> Ordering;
+}
+
+/// A number of words
+impl AllowedRange for usize {
+    fn compare(&self, from: usize, to: usize) -> Ordering {
+        (to - from).cmp(self)
+    }
+}
+
+/// A number of seconds between when words start, or a number of words (like [`usize`]) when either
+/// word isn't timed
+#[derive(Copy, Clone, Debug)]
+pub struct TimedRange<'a> {
+    pub seconds: f64,
+    pub words: usize,
+    pub transcript_words: &'a [IndexedWord],
+}
+
+impl AllowedRange for TimedRange<'_> {
+    fn compare(&self, from: usize, to: usize) -> Ordering {
+        let time = |idx: usize| {
+            let word = self.transcript_words.get(idx)?;
+            word.start.or(word.end)
+        };
+        match (time(from), time(to)) {
+            (Some(from), Some(to)) => (to - from).total_cmp(&self.seconds),
+            _ => self.words.compare(from, to),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Item<I: Iterator<Item = usize>> {
+    arr: I,
+    current: usize,
     pub word_id: WordId,
 }
 
-impl<'a> Item<'a> {
-    fn new(arr: &'a Vec<usize>, idx: usize, word_id: WordId) -> Self {
-        Self { arr, idx, word_id }
+impl<I: Iterator<Item = usize>> Item<I> {
+    /// [`None`] when there are no word indices
+    fn new(mut arr: I, word_id: WordId) -> Option<Self> {
+        let current = arr.next()?;
+        Some(Self {
+            arr,
+            current,
+            word_id,
+        })
     }
 
     #[inline]
     fn get_item(&self) -> usize {
-        self.arr[self.idx]
+        self.current
     }
 
     #[inline]
     fn get_pair(&self) -> (usize, WordId) {
-        (self.arr[self.idx], self.word_id)
+        (self.current, self.word_id)
+    }
+
+    /// Move on to the next word index, returning whether there was one
+    #[inline]
+    fn advance(&mut self) -> bool {
+        match self.arr.next() {
+            Some(next) => {
+                self.current = next;
+                true
+            }
+            None => false,
+        }
     }
 }
 
-impl<'a> PartialEq for Item<'a> {
+impl<I: Iterator<Item = usize>> Eq for Item<I> {}
+
+impl<I: Iterator<Item = usize>> PartialEq for Item<I> {
     fn eq(&self, other: &Self) -> bool {
         self.get_item() == other.get_item()
     }
 }
 
-impl<'a> PartialOrd for Item<'a> {
+impl<I: Iterator<Item = usize>> PartialOrd for Item<I> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.get_item().partial_cmp(&other.get_item())
+        Some(self.cmp(other))
     }
 }
 
-impl<'a> Ord for Item<'a> {
+impl<I: Iterator<Item = usize>> Ord for Item<I> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.get_item().cmp(&other.get_item())
     }
@@ -55,27 +110,93 @@ pub struct WordSegmentRange {
     pub min: usize,
     pub max: usize,
     pub elements: Vec<usize>,
+    /// The [`WordId`] of each of the `elements`
+    pub words: Vec<WordId>,
     pub set: WordIdSet,
+    /// See [`WordSegmentRange::ordering`]
+    #[serde(default)]
+    pub ordering: f64,
+    /// See [`Relevance::score`](crate::relevance::Relevance::score)
+    #[serde(default)]
+    pub score: f64,
 }
 
+/// How much each word between the matched words (that isn't itself matched) lowers the ordering
+const GAP_PENALTY: f64 = 0.1;
+
 impl WordSegmentRange {
     pub fn new(first_element: usize, word_id: WordId) -> Self {
         Self {
             min: first_element,
             max: first_element,
             elements: vec![first_element],
+            words: vec![word_id],
             set: WordIdSet::new(word_id),
+            ordering: 0.0,
+            score: 0.0,
         }
     }
 
+    /**
+    How closely the matched words follow the order of the query, from 0 to 1
+    - This is the share of unique words that are in query order (the longest run of them, not
+      necessarily adjacent, whose [`WordId`]s increase), lowered for every unmatched word in between
+    - A range with every query word in order and nothing between them scores 1
+    */
+    pub fn ordering(&self) -> f64 {
+        // patience sorting: `tails[n]` is the lowest word that ends an increasing run of `n + 1`
+        let mut tails: Vec<usize> = vec![];
+        for word in self.words.iter().map(|word| word.to_index()) {
+            let position = tails.partition_point(|tail| *tail < word);
+            if position == tails.len() {
+                tails.push(word);
+            } else {
+                tails[position] = word;
+            }
+        }
+        let in_order = tails.len() as f64 / self.set.unique_count() as f64;
+        let gaps = (self.total_range() + 1).saturating_sub(self.elements.len());
+        in_order / (1.0 + gaps as f64 * GAP_PENALTY)
+    }
+
+    /**
+    Also count the rest of the words of each phrase as elements, since only the first word of a
+    phrase is merged
+    - `length`: how many words the match of a [`WordId`] that starts at an element is
+    */
+    pub fn expand_phrases(&mut self, length: impl Fn(usize, WordId) -> usize) {
+        let mut expanded: Vec<(usize, WordId)> = self
+            .elements
+            .iter()
+            .zip(self.words.iter())
+            .flat_map(|(element, word_id)| {
+                let length = length(*element, *word_id);
+                (*element..element + length).map(move |element| (element, *word_id))
+            })
+            .collect();
+        expanded.sort_by_key(|(element, _)| *element);
+        expanded.dedup();
+        if let Some((last, _)) = expanded.last() {
+            self.max = self.max.max(*last);
+        }
+        (self.elements, self.words) = expanded.into_iter().unzip();
+    }
+
     pub fn total_range(&self) -> usize {
         self.max - self.min
     }
 
-    pub fn add(&mut self, element: usize, allowed_range: usize) -> bool {
+    pub fn add(
+        &mut self,
+        element: usize,
+        word_id: WordId,
+        allowed_range: &impl AllowedRange,
+    ) -> bool {
         let can_add = self.can_add(element, allowed_range);
         if can_add {
             self.elements.push(element);
+            self.words.push(word_id);
+            self.set.add(word_id);
             if element < self.min {
                 self.min = element;
             } else if element > self.max {
@@ -86,24 +207,31 @@ impl WordSegmentRange {
     }
 
     /// return whether or not the value can be added
-    pub fn can_add(&self, element: usize, allowed_range: usize) -> bool {
+    pub fn can_add(&self, element: usize, allowed_range: &impl AllowedRange) -> bool {
         if element < self.min {
-            let lowest_min = self.max - allowed_range;
-            element > lowest_min
+            allowed_range.compare(element, self.max) == Ordering::Less
         } else {
-            let highest_max = self.min + allowed_range;
-            element < highest_max
+            allowed_range.compare(self.min, element) == Ordering::Less
         }
     }
 }
 
-pub fn merge_special(arrays: Vec<Vec<usize>>, allowed_range: usize) -> Vec<WordSegmentRange> {
+/**
+Group the word indices of every query word into ranges where they are close together
+- `arrays`: the sorted word indices of each query word, whose position decides its [`WordId`] (so
+  pass an empty iterator for a word that wasn't found)
+*/
+pub fn merge_special<I: Iterator<Item = usize>>(
+    arrays: Vec<I>,
+    allowed_range: &impl AllowedRange,
+) -> Vec<WordSegmentRange> {
     let mut sorted: Vec<WordSegmentRange> = vec![];
 
-    let mut heap = BinaryHeap::with_capacity(arrays.len());
-    for (idx, arr) in arrays.iter().enumerate() {
-        let item = Item::new(arr, 0, WordId::from_index(idx));
-        heap.push(Reverse(item));
+    let mut heap = BinaryHeap::new();
+    for (idx, arr) in arrays.into_iter().enumerate() {
+        if let Some(item) = Item::new(arr, WordId::from_index(idx)) {
+            heap.push(Reverse(item));
+        }
     }
 
     while !heap.is_empty() {
@@ -115,10 +243,8 @@ pub fn merge_special(arrays: Vec<Vec<usize>>, allowed_range: usize) -> Vec<WordS
         let mut at_least_one_added = false;
         // while i can add to elements of the end of the array, do it!
         for range in sorted.iter_mut().rev() {
-            // try adding to the segment range
-            if range.add(this_index, allowed_range) {
-                // then add the word id to the set to keep track of unique elements
-                range.set.add(word_id);
+            // try adding to the segment range (which keeps track of unique elements)
+            if range.add(this_index, word_id, allowed_range) {
                 at_least_one_added = true;
             } else {
                 break;
@@ -130,7 +256,8 @@ pub fn merge_special(arrays: Vec<Vec<usize>>, allowed_range: usize) -> Vec<WordS
             if let Some(next) = heap.peek() {
                 let next_index = next.0.get_item();
                 let next_and_last_cant_reach = !last.can_add(next_index, allowed_range);
-                let this_and_last_can_reach = next_index.abs_diff(this_index) <= allowed_range;
+                let this_and_last_can_reach =
+                    allowed_range.compare(this_index, next_index) != Ordering::Greater;
                 // seriously, what is the !at_least_one_added doing here?
                 if (next_and_last_cant_reach && this_and_last_can_reach) || !at_least_one_added {
                     sorted.push(WordSegmentRange::new(this_index, word_id));
@@ -147,9 +274,8 @@ pub fn merge_special(arrays: Vec<Vec<usize>>, allowed_range: usize) -> Vec<WordS
         }
 
         // advance to next word id for the heap
-        this.idx += 1;
         // if elements remain, put back in heap
-        if it.0.idx < it.0.arr.len() {
+        if this.advance() {
             heap.push(it)
         }
     }
@@ -158,104 +284,126 @@ pub fn merge_special(arrays: Vec<Vec<usize>>, allowed_range: usize) -> Vec<WordS
 }
 
 /**
-- `required`: the LOGICAL AND of all required words (maybe this needs to be WordIdSet)
+[`merge_ranked`], but only keeping the ranges that have every required word
+- `required`: the [`WordId`]s of the required words
 */
-pub fn merge_required(
-    arrays: Vec<Vec<usize>>,
-    allowed_range: usize,
-    required: WordId,
+pub fn merge_required<I: Iterator<Item = usize>>(
+    arrays: Vec<I>,
+    allowed_range: &impl AllowedRange,
+    required: &WordIdSet,
 ) -> Vec<WordSegmentRange> {
-    todo!()
+    merge_ranked(arrays, allowed_range)
+        .into_iter()
+        .filter(|range| range.set.contains_all(required))
+        .collect()
 }
 
 /**
-- I need the ranking in terms of the order of WordIds
-- Question: can I combine multiple word ids to form a required? Yes
-- Question: can I combine multiple word ids to form a 1 is required? Yes
-I can do this by doing LOGICAL AND and making sure the 1 count > 0
-- I don't think I can rank different instances of the same word above each other (without big changes)
+[`merge_special`], with the ranges sorted from best to worst
+- Ranges with more unique words come first, then those that better follow the order of the query
+  (see [`WordSegmentRange::ordering`]), then those with more words
+- Ranges that tie stay in the order they were found
 */
-pub fn merge_ranked(arrays: Vec<Vec<usize>>, allowed_range: usize) -> Vec<WordSegmentRange> {
-    // let mut ranked: BTreeMap<u32, Vec<WordSegmentRange>> = BTreeMap::new();
-    // okay i dont think this is right, how am i actually ranking these?
-    // i need some kind of closure that calculates rank from word id
-    // maybe that will do LOGICAL AND and then count ones
-    let mut ranked: Vec<Vec<WordSegmentRange>> = vec![vec![]; 32];
-    let mut sorted = VecDeque::<WordSegmentRange>::new();
-
-    let mut heap = BinaryHeap::with_capacity(arrays.len());
-    for (idx, arr) in arrays.iter().enumerate() {
-        let item = Item::new(arr, 0, WordId::from_index(idx));
-        heap.push(Reverse(item));
+pub fn merge_ranked<I: Iterator<Item = usize>>(
+    arrays: Vec<I>,
+    allowed_range: &impl AllowedRange,
+) -> Vec<WordSegmentRange> {
+    let mut ranked = merge_special(arrays, allowed_range);
+    for range in ranked.iter_mut() {
+        range.ordering = range.ordering();
     }
+    ranked.sort_by(compare_ranges);
+    ranked
+}
 
-    while !heap.is_empty() {
-        let mut it = heap.pop().unwrap();
-        let this = &mut it.0;
-        let (this_index, word_id) = this.get_pair();
+/// Orders the better of two ranges first (see [`merge_ranked`])
+pub fn compare_ranges(a: &WordSegmentRange, b: &WordSegmentRange) -> Ordering {
+    b.set
+        .unique_count()
+        .cmp(&a.set.unique_count())
+        .then_with(|| b.ordering.total_cmp(&a.ordering))
+        .then_with(|| b.elements.len().cmp(&a.elements.len()))
+}
 
-        // ! evaluate what this is doing
-        let mut at_least_one_added = false;
-        let mut pop_count = 0;
-        // while i can add to elements of the end of the array, do it!
-        for range in sorted.iter_mut() {
-            // modify range to include new word found
-            if range.add(this_index, allowed_range) {
-                range.set.add(word_id);
-            }
-            // it is out of range, remove and give it ranking
-            else {
-                // add ranking
-                ranked[word_id.to_index()].push(range.clone());
-                // remove after iteration
-                pop_count += 1;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        for _ in 0..pop_count {
-            _ = sorted.pop_front();
-        }
+    fn word_ids(ids: &[usize]) -> WordIdSet {
+        ids.iter().map(|id| WordId::from_index(*id)).collect()
+    }
 
-        for range in sorted.iter_mut().rev() {
-            // try adding to the segment range
-            if range.add(this_index, allowed_range) {
-                // then add the word id to the set to keep track of unique elements
-                range.set.add(word_id);
-                at_least_one_added = true;
-            } else {
-                break;
-            }
+    /// A range of the query word at each position of `words`, found at `first` onwards, with its
+    /// ordering worked out
+    fn range(first: usize, words: &[usize]) -> WordSegmentRange {
+        let mut range = WordSegmentRange::new(first, WordId::from_index(words[0]));
+        for (offset, word) in words.iter().enumerate().skip(1) {
+            range.add(first + offset, WordId::from_index(*word), &usize::MAX);
         }
+        range.ordering = range.ordering();
+        range
+    }
 
-        // if the `next element` can't reach the `last element`, but the `next element` could reach `this element`, then i add `this element`
-        if let Some(last) = sorted.back() {
-            if let Some(next) = heap.peek() {
-                let next_index = next.0.get_item();
-                let next_and_last_cant_reach = !last.can_add(next_index, allowed_range);
-                let this_and_last_can_reach = next_index.abs_diff(this_index) <= allowed_range;
-                // seriously, what is the !at_least_one_added doing here?
-                if (next_and_last_cant_reach && this_and_last_can_reach) || !at_least_one_added {
-                    sorted.push_back(WordSegmentRange::new(this_index, word_id));
-                }
-            } else {
-                if !at_least_one_added {
-                    sorted.push_back(WordSegmentRange::new(this_index, word_id));
-                }
-            }
-        }
-        // the base case at the beginning with no last element
-        else {
-            sorted.push_back(WordSegmentRange::new(this_index, word_id));
-        }
+    /// The word indices of each range that [`merge_required`] keeps, where the query word at each
+    /// position is found at `positions`
+    fn required_ranges(
+        positions: &[&[usize]],
+        allowed_range: usize,
+        required: &[usize],
+    ) -> Vec<Vec<usize>> {
+        let arrays = positions
+            .iter()
+            .map(|indices| indices.iter().copied())
+            .collect();
+        merge_required(arrays, &allowed_range, &word_ids(required))
+            .into_iter()
+            .map(|range| range.elements)
+            .collect()
+    }
 
-        // advance to next word id for the heap
-        this.idx += 1;
-        // if elements remain, put back in heap
-        if it.0.idx < it.0.arr.len() {
-            heap.push(it)
-        }
+    #[test]
+    fn required_words_drop_the_ranges_without_them() {
+        // "grace" at 10, "faith" at 11 and 50, "works" at 51
+        let positions: &[&[usize]] = &[&[10], &[11, 50], &[51]];
+        assert_eq!(
+            required_ranges(positions, 5, &[]),
+            vec![vec![10, 11], vec![50, 51]]
+        );
+        assert_eq!(required_ranges(positions, 5, &[0]), vec![vec![10, 11]]);
+        assert_eq!(required_ranges(positions, 5, &[0, 1]), vec![vec![10, 11]]);
+        assert_eq!(required_ranges(positions, 5, &[1, 2]), vec![vec![50, 51]]);
+        assert!(required_ranges(positions, 5, &[0, 2]).is_empty());
+    }
+
+    #[test]
+    fn words_in_query_order_rank_higher() {
+        let in_order = range(10, &[0, 1, 2]);
+        let out_of_order = range(10, &[2, 1, 0]);
+        let partly_in_order = range(10, &[0, 2, 1]);
+        assert_eq!(in_order.ordering, 1.0);
+        assert_eq!(out_of_order.ordering, 1.0 / 3.0);
+        assert_eq!(partly_in_order.ordering, 2.0 / 3.0);
+        assert_eq!(compare_ranges(&in_order, &out_of_order), Ordering::Less);
+        assert_eq!(
+            compare_ranges(&partly_in_order, &out_of_order),
+            Ordering::Less
+        );
+        // more of the query's words still come first
+        assert_eq!(
+            compare_ranges(&out_of_order, &range(10, &[0, 1])),
+            Ordering::Less
+        );
     }
 
-    // sorted
-    todo!()
+    #[test]
+    fn gaps_lower_the_ordering() {
+        let adjacent = range(10, &[0, 1]);
+        let mut gappy = WordSegmentRange::new(10, WordId::from_index(0));
+        gappy.add(13, WordId::from_index(1), &5);
+        gappy.ordering = gappy.ordering();
+        // two unmatched words between them
+        assert_eq!(gappy.ordering, 1.0 / (1.0 + 2.0 * GAP_PENALTY));
+        assert_eq!(compare_ranges(&adjacent, &gappy), Ordering::Less);
+        assert_eq!(compare_ranges(&gappy, &adjacent), Ordering::Greater);
+    }
 }
@@ -0,0 +1,339 @@
+/// The number words below twenty, by their value
+const UNITS: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+/// [`UNITS`], as ordinals
+const UNIT_ORDINALS: [&str; 20] = [
+    "zeroth",
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+];
+/// The number words for twenty to ninety
+const TENS: [&str; 8] = [
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+/// [`TENS`], as ordinals
+const TENS_ORDINALS: [&str; 8] = [
+    "twentieth",
+    "thirtieth",
+    "fortieth",
+    "fiftieth",
+    "sixtieth",
+    "seventieth",
+    "eightieth",
+    "ninetieth",
+];
+
+/// A number, and whether it is an ordinal ("eighth" or "8th" rather than "eight" or "8")
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Number {
+    value: u32,
+    is_ordinal: bool,
+}
+
+impl Number {
+    fn cardinal(value: u32) -> Self {
+        Self {
+            value,
+            is_ordinal: false,
+        }
+    }
+
+    fn as_ordinal(self) -> Self {
+        Self {
+            is_ordinal: true,
+            ..self
+        }
+    }
+
+    /// Digits, with `st`, `nd`, `rd` or `th` after them for an ordinal
+    fn token(&self) -> String {
+        if !self.is_ordinal {
+            return self.value.to_string();
+        }
+        let suffix = match (self.value % 10, self.value % 100) {
+            (_, 11..=13) => "th",
+            (1, _) => "st",
+            (2, _) => "nd",
+            (3, _) => "rd",
+            _ => "th",
+        };
+        format!("{}{suffix}", self.value)
+    }
+}
+
+/// The position of `word` in `cardinals` or `ordinals`
+fn find_number(word: &str, cardinals: &[&str], ordinals: &[&str]) -> Option<Number> {
+    let find = |words: &[&str]| words.iter().position(|number| *number == word);
+    if let Some(value) = find(cardinals) {
+        return Some(Number::cardinal(value as u32));
+    }
+    find(ordinals).map(|value| Number {
+        value: value as u32,
+        is_ordinal: true,
+    })
+}
+
+/// The value of a number word below a hundred, such as "eight", "twenty" or "twentyeight" (which is
+/// "twenty-eight" once normalized)
+fn small_number(word: &str) -> Option<Number> {
+    if let Some(unit) = find_number(word, &UNITS, &UNIT_ORDINALS) {
+        return Some(unit);
+    }
+    if let Some(tens) = find_number(word, &TENS, &TENS_ORDINALS) {
+        return Some(Number {
+            value: 20 + 10 * tens.value,
+            ..tens
+        });
+    }
+    TENS.iter().enumerate().find_map(|(idx, tens)| {
+        let unit = find_number(word.strip_prefix(tens)?, &UNITS[..10], &UNIT_ORDINALS[..10])?;
+        Some(Number {
+            value: 20 + 10 * idx as u32 + unit.value,
+            ..unit
+        })
+    })
+}
+
+/// Digits such as "1000" ("1,000" once normalized), or an ordinal such as "21st"
+fn written_number(word: &str) -> Option<Number> {
+    let digits = word.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let is_ordinal = match &word[digits.len()..] {
+        "" => false,
+        "st" | "nd" | "rd" | "th" => true,
+        _ => return None,
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(Number {
+        value: digits.parse().ok()?,
+        is_ordinal,
+    })
+}
+
+/// A spoken number below a thousand (such as "one hundred and nineteen" or "twenty first"), and how
+/// many words it is
+fn spoken_hundreds(words: &[String]) -> Option<(Number, usize)> {
+    let word = |idx: usize| words.get(idx).map(String::as_str);
+    let mut number = Number::cardinal(0);
+    let mut used = 0;
+    let hundreds = match word(0)? {
+        "a" => Some(Number::cardinal(1)),
+        first => small_number(first).filter(|hundreds| !hundreds.is_ordinal && hundreds.value > 0),
+    };
+    if let Some(hundreds) = hundreds {
+        // "nineteen hundred" is a year
+        match word(1) {
+            Some("hundred") => {
+                number.value = hundreds.value * 100;
+                used = 2;
+            }
+            Some("hundredth") => {
+                let value = hundreds.value * 100;
+                return Some((Number::cardinal(value).as_ordinal(), 2));
+            }
+            _ => {}
+        }
+        // "one hundred and nineteen", but not "one hundred and then"
+        if used == 2 && word(2) == Some("and") && word(3).and_then(small_number).is_some() {
+            used = 3;
+        }
+    }
+    if let Some(small) = word(used).and_then(small_number) {
+        number = Number {
+            value: number.value + small.value,
+            is_ordinal: small.is_ordinal,
+        };
+        used += 1;
+        // "twenty eight" and "twenty first"
+        let unit = word(used)
+            .and_then(small_number)
+            .filter(|unit| (1..10).contains(&unit.value));
+        let is_tens = !small.is_ordinal && small.value >= 20 && small.value % 10 == 0;
+        if let Some(unit) = unit.filter(|_| is_tens) {
+            number = Number {
+                value: number.value + unit.value,
+                is_ordinal: unit.is_ordinal,
+            };
+            used += 1;
+        }
+    }
+    (used > 0).then_some((number, used))
+}
+
+/**
+A spoken number below a million, and how many words it is
+- `with_years`: also years where the century is said on its own, such as "nineteen eighty four",
+  "twenty twenty" or "nineteen oh five"
+*/
+fn spoken_number(words: &[String], with_years: bool) -> Option<(Number, usize)> {
+    let word = |idx: usize| words.get(idx).map(String::as_str);
+    let (mut number, mut used) = match (word(0), word(1)) {
+        (Some("a"), Some("thousand" | "thousandth")) => (Number::cardinal(1), 1),
+        _ => spoken_hundreds(words)?,
+    };
+    if number.is_ordinal {
+        return Some((number, used));
+    }
+    match word(used) {
+        Some("thousand") => {
+            number.value *= 1000;
+            used += 1;
+            let and = usize::from(word(used) == Some("and"));
+            if let Some((rest, length)) = spoken_hundreds(&words[used + and..]) {
+                number = Number {
+                    value: number.value + rest.value,
+                    is_ordinal: rest.is_ordinal,
+                };
+                used += and + length;
+            }
+        }
+        Some("thousandth") => {
+            return Some((Number::cardinal(number.value * 1000).as_ordinal(), used + 1))
+        }
+        // years, where the century is said on its own
+        Some(_) if with_years && used == 1 && (11..=20).contains(&number.value) => {
+            let is_oh = word(1) == Some("oh");
+            let year =
+                spoken_hundreds(&words[1 + usize::from(is_oh)..]).filter(|(year, length)| {
+                    let range = if is_oh { 1..10 } else { 10..100 };
+                    !year.is_ordinal && *length <= 2 && range.contains(&year.value)
+                });
+            if let Some((year, length)) = year {
+                number.value = number.value * 100 + year.value;
+                used += usize::from(is_oh) + length;
+            }
+        }
+        _ => {}
+    }
+    Some((number, used))
+}
+
+/**
+The number that `words` start with, and how many of the words it is
+- Either digits ("28") or spoken, such as "eight", "twenty eight" or "one hundred and nineteen" (below
+  a million), but not ordinals or years such as "nineteen eighty four"
+- `words` are already normalized (see [`normalize_word`](crate::searcher::normalize_word))
+*/
+pub fn parse_number(words: &[String]) -> Option<(u32, usize)> {
+    let (number, length) = match written_number(words.first()?) {
+        Some(number) => (number, 1),
+        None => spoken_number(words, false)?,
+    };
+    (!number.is_ordinal).then_some((number.value, length))
+}
+
+/**
+The token that the number `words` start with is indexed as, and how many of the words it is
+- This is its digits, such as "12" for "twelve", "1000" for "1,000" or "1984" for "nineteen eighty
+  four", and for ordinals with `st`, `nd`, `rd` or `th` after them, such as "21st" for "twenty-first"
+- `words` are already normalized (see [`normalize_word`](crate::searcher::normalize_word))
+*/
+pub fn number_token(words: &[String]) -> Option<(String, usize)> {
+    let (number, length) = match written_number(words.first()?) {
+        Some(number) => (number, 1),
+        None => spoken_number(words, true)?,
+    };
+    Some((number.token(), length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    fn token(text: &str) -> Option<(String, usize)> {
+        number_token(&words(text))
+    }
+
+    #[test]
+    fn spoken_numbers() {
+        assert_eq!(token("twelve disciples"), Some(("12".to_string(), 1)));
+        assert_eq!(token("twenty one elders"), Some(("21".to_string(), 2)));
+        assert_eq!(token("twentyone"), Some(("21".to_string(), 1)));
+        assert_eq!(
+            token("one hundred and nineteen"),
+            Some(("119".to_string(), 4))
+        );
+        assert_eq!(token("one hundred and then"), Some(("100".to_string(), 2)));
+        assert_eq!(token("a thousand men"), Some(("1000".to_string(), 2)));
+        assert_eq!(
+            token("two thousand and twenty"),
+            Some(("2020".to_string(), 4))
+        );
+        assert_eq!(token("grace"), None);
+    }
+
+    #[test]
+    fn ordinals() {
+        assert_eq!(token("twenty first"), Some(("21st".to_string(), 2)));
+        assert_eq!(token("second"), Some(("2nd".to_string(), 1)));
+        assert_eq!(token("eleventh"), Some(("11th".to_string(), 1)));
+        assert_eq!(token("one hundredth"), Some(("100th".to_string(), 2)));
+        assert_eq!(token("21st"), Some(("21st".to_string(), 1)));
+    }
+
+    #[test]
+    fn years() {
+        assert_eq!(token("nineteen oh five"), Some(("1905".to_string(), 3)));
+        assert_eq!(token("nineteen eighty four"), Some(("1984".to_string(), 3)));
+        assert_eq!(token("twenty twenty"), Some(("2020".to_string(), 2)));
+        assert_eq!(token("nineteen hundred"), Some(("1900".to_string(), 2)));
+    }
+
+    #[test]
+    fn written_numbers() {
+        assert_eq!(token("1000"), Some(("1000".to_string(), 1)));
+        assert_eq!(token("mp3"), None);
+        assert_eq!(token("3rd"), Some(("3rd".to_string(), 1)));
+        assert_eq!(token("3x"), None);
+    }
+
+    #[test]
+    fn parse_number_leaves_out_years_and_ordinals() {
+        assert_eq!(parse_number(&words("twenty eight")), Some((28, 2)));
+        assert_eq!(parse_number(&words("eight twenty")), Some((8, 1)));
+        assert_eq!(parse_number(&words("first")), None);
+        assert_eq!(parse_number(&words("28")), Some((28, 1)));
+    }
+}
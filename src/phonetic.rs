@@ -0,0 +1,163 @@
+/// Shorter words aren't matched by sound, since too many other short words sound like them
+pub const MIN_PHONETIC_LENGTH: usize = 4;
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+/**
+How a word sounds, so that words that sound alike (such as "Zacchaeus" and "Zakias") have the same key
+- This is [Metaphone](https://en.wikipedia.org/wiki/Metaphone), with `cch` and `chr` sounding like `k`
+  as they do in names such as "Zacchaeus" and "Christ"
+- Letters other than `a` to `z` are ignored, and `0` stands for `th`
+- `word` is already normalized (see [`normalize_word`](crate::searcher::normalize_word))
+*/
+pub fn phonetic_key(word: &str) -> String {
+    let mut letters: Vec<char> = word.chars().filter(|c| c.is_ascii_lowercase()).collect();
+    // doubled letters sound like one of them (except `cc`, as in "accept")
+    letters.dedup_by(|c, previous| c == previous && *c != 'c');
+    // silent first letters
+    if letters.len() > 1 {
+        match (letters[0], letters[1]) {
+            ('k' | 'g' | 'p', 'n') | ('w', 'r') | ('p', 's') => _ = letters.remove(0),
+            ('a', 'e') => _ = letters.remove(0),
+            ('w', 'h') => _ = letters.remove(1),
+            _ => {}
+        }
+    }
+    if letters.first() == Some(&'x') {
+        letters[0] = 's';
+    }
+
+    let at = |idx: usize| letters.get(idx).copied();
+    let mut key = String::new();
+    let mut idx = 0;
+    while idx < letters.len() {
+        let c = letters[idx];
+        let previous = idx.checked_sub(1).and_then(at);
+        let next = at(idx + 1);
+        let after_next = at(idx + 2);
+        match c {
+            'a' | 'e' | 'i' | 'o' | 'u' => {
+                if idx == 0 {
+                    key.push('A');
+                }
+            }
+            'b' => {
+                if !(previous == Some('m') && next.is_none()) {
+                    key.push('B');
+                }
+            }
+            'c' => {
+                if next == Some('c') && after_next == Some('h') {
+                    key.push('K');
+                    idx += 2;
+                } else if next == Some('i') && after_next == Some('a') {
+                    key.push('X');
+                } else if next == Some('h') {
+                    let is_hard = previous == Some('s') || after_next == Some('r');
+                    key.push(if is_hard { 'K' } else { 'X' });
+                } else if matches!(next, Some('i' | 'e' | 'y')) {
+                    if previous != Some('s') {
+                        key.push('S');
+                    }
+                } else {
+                    key.push('K');
+                }
+            }
+            'd' => {
+                if next == Some('g') && matches!(after_next, Some('e' | 'i' | 'y')) {
+                    key.push('J');
+                    idx += 1;
+                } else {
+                    key.push('T');
+                }
+            }
+            'g' => {
+                let is_silent = (next == Some('h') && !after_next.is_some_and(is_vowel))
+                    || (next == Some('n')
+                        && (after_next.is_none() || letters[idx + 2..] == ['e', 'd']));
+                if !is_silent {
+                    let is_soft = matches!(next, Some('i' | 'e' | 'y'));
+                    key.push(if is_soft { 'J' } else { 'K' });
+                }
+            }
+            'h' => {
+                let is_silent = matches!(previous, Some('c' | 's' | 'p' | 't' | 'g'))
+                    || (previous.is_some_and(is_vowel) && !next.is_some_and(is_vowel));
+                if !is_silent {
+                    key.push('H');
+                }
+            }
+            'k' => {
+                if previous != Some('c') {
+                    key.push('K');
+                }
+            }
+            'p' => key.push(if next == Some('h') { 'F' } else { 'P' }),
+            'q' => key.push('K'),
+            's' => {
+                let is_sh = next == Some('h')
+                    || (next == Some('i') && matches!(after_next, Some('o' | 'a')));
+                key.push(if is_sh { 'X' } else { 'S' });
+            }
+            't' => {
+                if next == Some('i') && matches!(after_next, Some('o' | 'a')) {
+                    key.push('X');
+                } else if next == Some('h') {
+                    key.push('0');
+                } else if !(next == Some('c') && after_next == Some('h')) {
+                    key.push('T');
+                }
+            }
+            'v' => key.push('F'),
+            'w' | 'y' => {
+                if next.is_some_and(is_vowel) {
+                    key.push(c.to_ascii_uppercase());
+                }
+            }
+            'x' => key.push_str("KS"),
+            'z' => key.push('S'),
+            _ => key.push(c.to_ascii_uppercase()),
+        }
+        idx += 1;
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sound_alike(a: &str, b: &str) -> bool {
+        phonetic_key(a) == phonetic_key(b)
+    }
+
+    #[test]
+    fn names_that_sound_alike() {
+        assert!(sound_alike("zacchaeus", "zakias"));
+        assert!(sound_alike("nebuchadnezzar", "nebuchadnezar"));
+        assert!(sound_alike("phillip", "filip"));
+        assert!(sound_alike("christ", "kryst"));
+        assert!(sound_alike("aaron", "aron"));
+    }
+
+    #[test]
+    fn metaphone_keys() {
+        assert_eq!(phonetic_key("smith"), "SM0");
+        assert_eq!(phonetic_key("smyth"), "SM0");
+        assert_eq!(phonetic_key("knight"), "NT");
+        assert_eq!(phonetic_key("night"), "NT");
+        assert_eq!(phonetic_key("wright"), "RT");
+        assert_eq!(phonetic_key("psalm"), "SLM");
+        assert_eq!(phonetic_key("xerxes"), "SRKSS");
+        assert_eq!(phonetic_key("aaron"), "ARN");
+    }
+
+    #[test]
+    fn different_sounds_have_different_keys() {
+        assert!(!sound_alike("jesus", "cheeses"));
+        assert!(!sound_alike("wholly", "holy"));
+        assert!(!sound_alike("grace", "greek"));
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+/**
+The word indices of one word in one transcript, stored compactly
+- Indices are sorted, so each one is stored as the gap from the previous one (the first is stored as
+  is), encoded as a varint: 7 bits per byte, with the high bit set on every byte but the last
+- Most gaps fit in a single byte, where a `Vec<usize>` would take 8
+- The bytes are shared by every copy, so that copying a word's postings (when another transcript with
+  it changes) doesn't copy them
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Postings {
+    bytes: Arc<[u8]>,
+    len: usize,
+}
+
+impl Postings {
+    /// - `indices` must be sorted in ascending order without duplicates
+    pub fn from_sorted(indices: &[usize]) -> Self {
+        debug_assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        let mut bytes = Vec::with_capacity(indices.len());
+        let mut previous = 0;
+        for &index in indices {
+            let mut gap = index - previous;
+            while gap >= 0x80 {
+                bytes.push((gap as u8 & 0x7f) | 0x80);
+                gap >>= 7;
+            }
+            bytes.push(gap as u8);
+            previous = index;
+        }
+        Self {
+            bytes: bytes.into(),
+            len: indices.len(),
+        }
+    }
+
+    /// How many word indices there are
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The word indices in ascending order
+    pub fn iter(&self) -> PostingsIter<'_> {
+        PostingsIter {
+            bytes: &self.bytes,
+            previous: 0,
+        }
+    }
+
+    /// This decodes up to `index`, so prefer walking [`Postings::iter`] alongside other sorted
+    /// indices when checking many of them
+    pub fn contains(&self, index: usize) -> bool {
+        self.iter()
+            .take_while(|&other| other <= index)
+            .any(|other| other == index)
+    }
+
+    pub fn to_vec(&self) -> Vec<usize> {
+        self.iter().collect()
+    }
+
+    /// How many bytes the encoded indices take up
+    pub fn heap_size(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PostingsIter<'a> {
+    bytes: &'a [u8],
+    previous: usize,
+}
+
+impl Iterator for PostingsIter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut gap = 0;
+        let mut shift = 0;
+        loop {
+            let (byte, rest) = self.bytes.split_first()?;
+            self.bytes = rest;
+            gap |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        self.previous += gap;
+        Some(self.previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(indices: &[usize]) {
+        let postings = Postings::from_sorted(indices);
+        assert_eq!(postings.to_vec(), indices);
+        assert_eq!(postings.len(), indices.len());
+        assert_eq!(postings.is_empty(), indices.is_empty());
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(&[]);
+        round_trip(&[0]);
+        round_trip(&[5]);
+        round_trip(&[0, 1, 2, 3]);
+        round_trip(&[3, 200, 201, 70_000, 70_127, 70_128]);
+        round_trip(&[usize::MAX >> 1, usize::MAX]);
+    }
+
+    #[test]
+    fn gaps_take_a_byte_per_7_bits() {
+        assert_eq!(Postings::from_sorted(&[0x7f]).bytes[..], [0x7f]);
+        assert_eq!(Postings::from_sorted(&[0x80]).bytes[..], [0x80, 0x01]);
+        assert_eq!(
+            Postings::from_sorted(&[1, 0x81, 0x4081]).bytes[..],
+            [0x01, 0x80, 0x01, 0x80, 0x80, 0x01]
+        );
+        let gaps: Vec<usize> = (0..1000).map(|gap| gap * 3).collect();
+        assert!(Postings::from_sorted(&gaps).heap_size() <= gaps.len() + 1);
+    }
+
+    #[test]
+    fn contains() {
+        let postings = Postings::from_sorted(&[2, 130, 131, 5000]);
+        for index in [2, 130, 131, 5000] {
+            assert!(postings.contains(index));
+        }
+        for index in [0, 3, 129, 132, 4999, 5001] {
+            assert!(!postings.contains(index));
+        }
+    }
+}
@@ -0,0 +1,567 @@
+use serde::Serialize;
+
+use crate::numbers::number_token;
+use crate::searcher::normalize_word;
+
+/// Stands for any number of letters in a query word, such as `redeem*`
+pub const WILDCARD: char = '*';
+
+/// Whether a query term has to be in a result
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Presence {
+    /// The term counts towards a result, but isn't needed
+    Optional,
+    /// `+word`: every result has the term
+    Required,
+    /// `-word`: no result has the term
+    Excluded,
+}
+
+/// One word (or quoted phrase) of a search query
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueryTerm {
+    /// The normalized words (see [`normalize_word`]), where a phrase has more than one that have to
+    /// be found one after another
+    pub words: Vec<String>,
+    pub presence: Presence,
+}
+
+impl QueryTerm {
+    pub fn is_phrase(&self) -> bool {
+        self.words.len() > 1
+    }
+
+    /// A single word with a [`WILDCARD`] in it
+    pub fn is_wildcard(&self) -> bool {
+        !self.is_phrase() && self.words[0].contains(WILDCARD)
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.presence == Presence::Required
+    }
+
+    pub fn is_excluded(&self) -> bool {
+        self.presence == Presence::Excluded
+    }
+}
+
+/**
+Split a query into its terms
+- Words in double quotes form one phrase, such as `"by this" evident love` (an unclosed quote runs to
+  the end of the query)
+- Unquoted words can have [`WILDCARD`]s in them, such as `redeem*` (the words of a phrase can't)
+- A leading `+` makes a term required and a leading `-` excludes it, such as `+grace -"the law"`
+- Terms that are empty once normalized (such as a lone `-`) are left out
+- Numbers become the digits they are indexed as (see [`normalize_numbers`])
+*/
+pub fn parse_query(query: &str) -> Vec<QueryTerm> {
+    let mut terms = vec![];
+    let mut rest = query.trim_start();
+    while !rest.is_empty() {
+        let (presence, term) = if let Some(term) = rest.strip_prefix('+') {
+            (Presence::Required, term)
+        } else if let Some(term) = rest.strip_prefix('-') {
+            (Presence::Excluded, term)
+        } else {
+            (Presence::Optional, rest)
+        };
+        let (text, remaining, quoted) = match term.strip_prefix('"') {
+            Some(quoted) => {
+                let (text, remaining) = quoted.split_once('"').unwrap_or((quoted, ""));
+                (text, remaining, true)
+            }
+            None => {
+                let (text, remaining) = term.split_once(char::is_whitespace).unwrap_or((term, ""));
+                (text, remaining, false)
+            }
+        };
+        let mut words: Vec<String> = if quoted {
+            text.split_whitespace().map(normalize_word).collect()
+        } else {
+            vec![normalize_wildcard(text)]
+        };
+        words.retain(|word| !word.is_empty());
+        if !words.is_empty() {
+            terms.push(QueryTerm { words, presence });
+        }
+        rest = remaining.trim_start();
+    }
+    normalize_numbers(terms)
+}
+
+/**
+Replace numbers with the digits that they are indexed as (see
+[`index_tokens`](crate::hsk_file::index_tokens)), so that `twelve disciples` also finds "12
+disciples"
+- A number of several words (such as `twenty one`) becomes one term, when they have the same prefix
+- In a phrase, the first word of a number is replaced and the rest are kept, as they are indexed
+*/
+fn normalize_numbers(terms: Vec<QueryTerm>) -> Vec<QueryTerm> {
+    let mut normalized = vec![];
+    let mut idx = 0;
+    while idx < terms.len() {
+        let term = &terms[idx];
+        if term.is_phrase() {
+            normalized.push(QueryTerm {
+                words: phrase_numbers(&term.words),
+                presence: term.presence,
+            });
+            idx += 1;
+            continue;
+        }
+        let words: Vec<String> = terms[idx..]
+            .iter()
+            .take_while(|other| {
+                !other.is_phrase() && !other.is_wildcard() && other.presence == term.presence
+            })
+            .map(|other| other.words[0].clone())
+            .collect();
+        match number_token(&words) {
+            Some((token, length)) => {
+                normalized.push(QueryTerm {
+                    words: vec![token],
+                    presence: term.presence,
+                });
+                idx += length;
+            }
+            None => {
+                normalized.push(term.clone());
+                idx += 1;
+            }
+        }
+    }
+    normalized
+}
+
+/// Each word of a phrase that starts a number replaced with its digits (see [`normalize_numbers`])
+fn phrase_numbers(words: &[String]) -> Vec<String> {
+    words
+        .iter()
+        .enumerate()
+        .map(|(idx, word)| {
+            number_token(&words[idx..]).map_or_else(|| word.clone(), |(token, _)| token)
+        })
+        .collect()
+}
+
+/// [`normalize_word`], but keeping the [`WILDCARD`]s (repeated ones are merged, and one on its own is
+/// left out since it would match everything)
+fn normalize_wildcard(word: &str) -> String {
+    let parts: Vec<String> = word.split(WILDCARD).map(normalize_word).collect();
+    let mut normalized = parts.join(&WILDCARD.to_string());
+    while normalized.contains("**") {
+        normalized = normalized.replace("**", "*");
+    }
+    if normalized.chars().all(|c| c == WILDCARD) {
+        normalized.clear();
+    }
+    normalized
+}
+
+/// A boolean query (see [`parse_boolean_query`]), where each [`QueryExpression::Term`] is the index
+/// of one of `terms`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BooleanQuery {
+    pub terms: Vec<QueryTerm>,
+    pub expression: QueryExpression,
+}
+
+/// A node of a [`BooleanQuery`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryExpression {
+    Term(usize),
+    And(Vec<QueryExpression>),
+    Or(Vec<QueryExpression>),
+    Not(Box<QueryExpression>),
+    /// The two sides are found at most this many words apart
+    Near(Box<QueryExpression>, Box<QueryExpression>, usize),
+}
+
+impl QueryExpression {
+    /// Adds the indices of the terms that are inside an odd number of [`QueryExpression::Not`]s
+    fn negated_terms(&self, negated: bool, terms: &mut Vec<usize>) {
+        match self {
+            Self::Term(idx) => {
+                if negated {
+                    terms.push(*idx);
+                }
+            }
+            Self::And(expressions) | Self::Or(expressions) => {
+                for expression in expressions {
+                    expression.negated_terms(negated, terms);
+                }
+            }
+            Self::Not(expression) => expression.negated_terms(!negated, terms),
+            Self::Near(left, right, _) => {
+                left.negated_terms(negated, terms);
+                right.negated_terms(negated, terms);
+            }
+        }
+    }
+
+    /// The furthest apart that any [`QueryExpression::Near`] allows its sides to be
+    pub fn max_near_distance(&self) -> usize {
+        match self {
+            Self::Term(_) => 0,
+            Self::And(expressions) | Self::Or(expressions) => expressions
+                .iter()
+                .map(|expression| expression.max_near_distance())
+                .max()
+                .unwrap_or_default(),
+            Self::Not(expression) => expression.max_near_distance(),
+            Self::Near(left, right, distance) => (*distance)
+                .max(left.max_near_distance())
+                .max(right.max_near_distance()),
+        }
+    }
+}
+
+impl BooleanQuery {
+    /// Whether each of the terms is only there to rule results out (it is after a `NOT`)
+    pub fn negated_terms(&self) -> Vec<bool> {
+        let mut negated = vec![];
+        self.expression.negated_terms(false, &mut negated);
+        (0..self.terms.len())
+            .map(|idx| negated.contains(&idx))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Near(usize),
+    Term(QueryTerm),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open => write!(f, "\"(\""),
+            Self::Close => write!(f, "\")\""),
+            Self::And => write!(f, "AND"),
+            Self::Or => write!(f, "OR"),
+            Self::Not => write!(f, "NOT"),
+            Self::Near(distance) => write!(f, "NEAR/{distance}"),
+            Self::Term(term) => write!(f, "\"{}\"", term.words.join(" ")),
+        }
+    }
+}
+
+const NEAR_PREFIX: &str = "NEAR/";
+
+/// The operators outside of quotes that make a query boolean (see [`is_boolean_query`])
+fn is_operator(word: &str) -> bool {
+    matches!(word, "AND" | "OR" | "NOT" | "NEAR") || word.starts_with(NEAR_PREFIX)
+}
+
+/// Whether a query uses `AND`, `OR`, `NOT`, `NEAR/n` or parentheses (outside of quotes), and so is
+/// parsed with [`parse_boolean_query`] instead of [`parse_query`]
+pub fn is_boolean_query(query: &str) -> bool {
+    query.split('"').step_by(2).any(|unquoted| {
+        unquoted.contains(['(', ')']) || unquoted.split_whitespace().any(is_operator)
+    })
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = query.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, remaining) = match c {
+            '(' => (Some(Token::Open), &rest[1..]),
+            ')' => (Some(Token::Close), &rest[1..]),
+            // `-word` is short for `NOT word`, and `+word` is just `word` since every word counts
+            '-' => (Some(Token::Not), &rest[1..]),
+            '+' => (None, &rest[1..]),
+            '"' => {
+                let (text, remaining) = rest[1..].split_once('"').unwrap_or((&rest[1..], ""));
+                let words: Vec<String> = text
+                    .split_whitespace()
+                    .map(normalize_word)
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                let token = (!words.is_empty()).then_some(Token::Term(QueryTerm {
+                    words: phrase_numbers(&words),
+                    presence: Presence::Optional,
+                }));
+                (token, remaining)
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '"'))
+                    .unwrap_or(rest.len());
+                let (word, remaining) = rest.split_at(end);
+                let token = match word {
+                    "AND" => Some(Token::And),
+                    "OR" => Some(Token::Or),
+                    "NOT" => Some(Token::Not),
+                    _ if is_operator(word) => {
+                        let distance = word
+                            .strip_prefix(NEAR_PREFIX)
+                            .and_then(|distance| distance.parse().ok())
+                            .ok_or_else(|| {
+                                format!("\"{word}\" needs a number of words, such as NEAR/5")
+                            })?;
+                        Some(Token::Near(distance))
+                    }
+                    // each word of a number on its own, which is how it is indexed too
+                    _ => {
+                        let word = phrase_numbers(&[normalize_wildcard(word)]).remove(0);
+                        (!word.is_empty()).then(|| {
+                            Token::Term(QueryTerm {
+                                words: vec![word],
+                                presence: Presence::Optional,
+                            })
+                        })
+                    }
+                };
+                (token, remaining)
+            }
+        };
+        tokens.extend(token);
+        rest = remaining.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// How deeply parentheses, `NOT`s and `NEAR/n`s can nest, so that a query can't overflow the stack
+const MAX_NESTING: usize = 64;
+
+/// A recursive descent parser, from the operator that binds loosest (`OR`) to the tightest (`NOT`)
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    terms: Vec<QueryTerm>,
+    /// How many parentheses, `NOT`s and `NEAR/n`s the next token is nested in
+    depth: usize,
+}
+
+impl Parser {
+    /// Go one level deeper (see [`MAX_NESTING`])
+    fn nest(&mut self) -> Result<(), String> {
+        if self.depth >= MAX_NESTING {
+            return Err(format!(
+                "A query can't nest parentheses, NOTs and NEARs more than {MAX_NESTING} deep"
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<QueryExpression, String> {
+        let mut expressions = vec![self.and()?];
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expressions.push(self.and()?);
+        }
+        Ok(flatten(expressions, QueryExpression::Or))
+    }
+
+    /// Words next to each other without an operator are joined by `AND`, so `faith NOT works` is
+    /// `faith AND NOT works`
+    fn and(&mut self) -> Result<QueryExpression, String> {
+        let mut expressions = vec![self.near()?];
+        loop {
+            match self.tokens.peek() {
+                Some(Token::And) => {
+                    self.tokens.next();
+                }
+                Some(Token::Open | Token::Not | Token::Term(_)) => {}
+                _ => break,
+            }
+            expressions.push(self.near()?);
+        }
+        Ok(flatten(expressions, QueryExpression::And))
+    }
+
+    /// Each `NEAR/n` nests everything before it one deeper
+    fn near(&mut self) -> Result<QueryExpression, String> {
+        let depth = self.depth;
+        let mut expression = self.unary()?;
+        while let Some(Token::Near(distance)) = self.tokens.peek() {
+            let distance = *distance;
+            self.tokens.next();
+            self.nest()?;
+            let right = self.unary()?;
+            expression = QueryExpression::Near(Box::new(expression), Box::new(right), distance);
+        }
+        self.depth = depth;
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<QueryExpression, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => {
+                self.nest()?;
+                let expression = self.unary()?;
+                self.depth -= 1;
+                Ok(QueryExpression::Not(Box::new(expression)))
+            }
+            Some(Token::Open) => {
+                self.nest()?;
+                let expression = self.or()?;
+                self.depth -= 1;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expression),
+                    _ => Err("A \"(\" is never closed".to_string()),
+                }
+            }
+            Some(Token::Term(term)) => {
+                self.terms.push(term);
+                Ok(QueryExpression::Term(self.terms.len() - 1))
+            }
+            Some(token) => Err(format!("Expected a word or \"(\" but found {token}")),
+            None => Err("The query ends where a word or \"(\" was expected".to_string()),
+        }
+    }
+}
+
+/// One expression on its own, or `combine` of all of them
+fn flatten(
+    mut expressions: Vec<QueryExpression>,
+    combine: fn(Vec<QueryExpression>) -> QueryExpression,
+) -> QueryExpression {
+    if expressions.len() == 1 {
+        expressions.remove(0)
+    } else {
+        combine(expressions)
+    }
+}
+
+/**
+Parse a query of words (and quoted phrases or wildcards, as in [`parse_query`]) joined by operators,
+such as `(grace OR mercy) AND faith NOT works` or `covenant NEAR/5 blood`
+- From the tightest binding: `NOT` (or `-`), `NEAR/n`, `AND` (or nothing), and then `OR`, with
+  parentheses for grouping
+- `NEAR/n`: both sides are at most `n` words apart
+- Nesting is limited to [`MAX_NESTING`] levels
+- The error explains what is wrong with the query, such as an unclosed parenthesis
+*/
+pub fn parse_boolean_query(query: &str) -> Result<BooleanQuery, String> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?.into_iter().peekable(),
+        terms: vec![],
+        depth: 0,
+    };
+    let expression = parser.or()?;
+    // everything but a `)` continues the expression
+    if parser.tokens.next().is_some() {
+        return Err("A \")\" has no \"(\" to close".to_string());
+    }
+    let query = BooleanQuery {
+        terms: parser.terms,
+        expression,
+    };
+    if query.negated_terms().iter().all(|negated| *negated) {
+        return Err("A query needs at least one word that isn't after NOT".to_string());
+    }
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(expression: &str) -> Vec<Vec<String>> {
+        parse_boolean_query(expression)
+            .expect("It parses")
+            .terms
+            .into_iter()
+            .map(|term| term.words)
+            .collect()
+    }
+
+    #[test]
+    fn operators_bind_from_not_to_or() {
+        let query = parse_boolean_query("grace OR mercy faith NOT works").expect("It parses");
+        assert_eq!(
+            query.expression,
+            QueryExpression::Or(vec![
+                QueryExpression::Term(0),
+                QueryExpression::And(vec![
+                    QueryExpression::Term(1),
+                    QueryExpression::Term(2),
+                    QueryExpression::Not(Box::new(QueryExpression::Term(3))),
+                ]),
+            ])
+        );
+        assert_eq!(query.negated_terms(), vec![false, false, false, true]);
+    }
+
+    #[test]
+    fn parentheses_group() {
+        let query = parse_boolean_query("(grace OR mercy) AND faith").expect("It parses");
+        assert_eq!(
+            query.expression,
+            QueryExpression::And(vec![
+                QueryExpression::Or(vec![QueryExpression::Term(0), QueryExpression::Term(1)]),
+                QueryExpression::Term(2),
+            ])
+        );
+    }
+
+    #[test]
+    fn near_has_a_distance() {
+        let query = parse_boolean_query("covenant NEAR/5 blood").expect("It parses");
+        assert_eq!(
+            query.expression,
+            QueryExpression::Near(
+                Box::new(QueryExpression::Term(0)),
+                Box::new(QueryExpression::Term(1)),
+                5
+            )
+        );
+        assert_eq!(query.expression.max_near_distance(), 5);
+        assert!(parse_boolean_query("covenant NEAR/ blood").is_err());
+    }
+
+    #[test]
+    fn phrases_and_dashes() {
+        assert_eq!(
+            words("\"by this\" -law"),
+            vec![
+                vec!["by".to_string(), "this".to_string()],
+                vec!["law".to_string()]
+            ]
+        );
+        let query = parse_boolean_query("grace -law").expect("It parses");
+        assert_eq!(query.negated_terms(), vec![false, true]);
+    }
+
+    #[test]
+    fn unclosed_parenthesis_is_an_error() {
+        assert_eq!(
+            parse_boolean_query("(a OR b").unwrap_err(),
+            "A \"(\" is never closed"
+        );
+        assert_eq!(
+            parse_boolean_query("a OR b)").unwrap_err(),
+            "A \")\" has no \"(\" to close"
+        );
+    }
+
+    #[test]
+    fn only_negated_words_is_an_error() {
+        assert_eq!(
+            parse_boolean_query("NOT a").unwrap_err(),
+            "A query needs at least one word that isn't after NOT"
+        );
+        assert!(parse_boolean_query("a OR").is_err());
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_boolean_query(&nested(MAX_NESTING)).is_ok());
+        assert!(parse_boolean_query(&nested(MAX_NESTING + 1)).is_err());
+        let nots = format!("a {}b", "NOT ".repeat(MAX_NESTING + 1));
+        assert!(parse_boolean_query(&nots).is_err());
+        let nears = format!("a{}", " NEAR/1 a".repeat(MAX_NESTING + 1));
+        assert!(parse_boolean_query(&nears).is_err());
+        let ands = format!("a{}", " AND a".repeat(MAX_NESTING + 1));
+        assert!(parse_boolean_query(&ands).is_ok());
+    }
+}
@@ -0,0 +1,182 @@
+use serde::Serialize;
+
+use crate::merge::WordSegmentRange;
+use crate::searcher::{normalize_word, TranscriptId, TranscriptWords};
+use crate::vocabulary::Vocabulary;
+use crate::word_id::WordId;
+
+/// How quickly repeating a word stops adding to its score (BM25's `k1`)
+const TERM_SATURATION: f64 = 1.2;
+/// How much a transcript's length lowers the score of the words in it (BM25's `b`)
+const LENGTH_NORMALIZATION: f64 = 0.75;
+/// How much a fuzzy match counts for compared to an exact one
+const FUZZY_WEIGHT: f64 = 0.5;
+
+/// Index-wide numbers that [`Relevance`] needs, refreshed whenever transcripts are loaded
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct TermStatistics {
+    pub transcript_count: usize,
+    /// In words
+    pub average_transcript_length: f64,
+}
+
+impl TermStatistics {
+    pub fn new(transcript_words: &TranscriptWords) -> Self {
+        let transcript_count = transcript_words.len();
+        let total_words: usize = transcript_words.values().map(|words| words.len()).sum();
+        Self {
+            transcript_count,
+            average_transcript_length: total_words as f64 / transcript_count.max(1) as f64,
+        }
+    }
+}
+
+/**
+Scores a segment by how relevant it is to a query, BM25-style
+- Rarer words count for more (inverse document frequency), as do words repeated in the segment, up to
+  a point (and less so in long transcripts)
+- This is scaled down for segments missing some of the query (weighted by how rare the missing words
+  are), segments with unmatched words between the matched ones, and segments out of query order
+- Fuzzy matches (by spelling or sound) only count for [`FUZZY_WEIGHT`] of an exact one
+*/
+#[derive(Clone, Debug)]
+pub struct Relevance<'a> {
+    /// The inverse document frequency of each query term, by [`WordId`](crate::word_id::WordId)
+    idfs: Vec<f64>,
+    /// The words that each query term only matches fuzzily (by spelling or sound)
+    fuzzy_words: Vec<Vec<String>>,
+    total_idf: f64,
+    statistics: &'a TermStatistics,
+    transcript_words: &'a TranscriptWords,
+    vocabulary: &'a Vocabulary,
+}
+
+impl<'a> Relevance<'a> {
+    /// - `document_frequencies`: how many transcripts have each query term, in query order
+    pub fn new(
+        document_frequencies: impl IntoIterator<Item = usize>,
+        fuzzy_words: Vec<Vec<String>>,
+        statistics: &'a TermStatistics,
+        transcript_words: &'a TranscriptWords,
+        vocabulary: &'a Vocabulary,
+    ) -> Self {
+        let transcript_count = statistics.transcript_count as f64;
+        let idfs: Vec<f64> = document_frequencies
+            .into_iter()
+            .map(|frequency| {
+                let frequency = frequency as f64;
+                (1.0 + (transcript_count - frequency + 0.5) / (frequency + 0.5)).ln()
+            })
+            .collect();
+        Self {
+            total_idf: idfs.iter().sum(),
+            idfs,
+            fuzzy_words,
+            statistics,
+            transcript_words,
+            vocabulary,
+        }
+    }
+
+    /// - `starts`: where each match of a term in the range starts (which is the range's elements
+    ///   before any phrases were expanded)
+    pub fn score(
+        &self,
+        transcript_id: TranscriptId,
+        range: &WordSegmentRange,
+        starts: &[(usize, WordId)],
+    ) -> f64 {
+        let transcript_words = self
+            .transcript_words
+            .get(&transcript_id)
+            .map_or(&[][..], |words| words.as_slice());
+        let transcript_length = transcript_words.len() as f64;
+        let average_length = self.statistics.average_transcript_length.max(1.0);
+        let length_normalization =
+            1.0 - LENGTH_NORMALIZATION + LENGTH_NORMALIZATION * transcript_length / average_length;
+
+        let mut term_frequencies = vec![0.0; self.idfs.len()];
+        for (element, word) in starts.iter() {
+            let fuzzy_words = &self.fuzzy_words[word.to_index()];
+            let is_fuzzy = !fuzzy_words.is_empty()
+                && transcript_words.get(*element).is_some_and(|indexed| {
+                    fuzzy_words.contains(&normalize_word(self.vocabulary.get(indexed.token)))
+                });
+            term_frequencies[word.to_index()] += if is_fuzzy { FUZZY_WEIGHT } else { 1.0 };
+        }
+        let mut rarity = 0.0;
+        let mut matched_idf = 0.0;
+        for (idf, frequency) in self.idfs.iter().zip(term_frequencies) {
+            if frequency == 0.0 {
+                continue;
+            }
+            rarity += idf * frequency * (TERM_SATURATION + 1.0)
+                / (frequency + TERM_SATURATION * length_normalization);
+            matched_idf += idf;
+        }
+
+        let coverage = if self.total_idf > 0.0 {
+            matched_idf / self.total_idf
+        } else {
+            1.0
+        };
+        let tightness = (range.elements.len() as f64 / (range.total_range() + 1) as f64).min(1.0);
+        rarity * coverage * (1.0 + tightness) / 2.0 * (1.0 + range.ordering) / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::searcher::tests::searcher_with;
+    use crate::synonyms::Synonyms;
+
+    /// The score of the match of the query term `word` at `element` of the first transcript
+    fn score_at(relevance: &Relevance, element: usize, word: usize) -> f64 {
+        let word = WordId::from_index(word);
+        let mut range = WordSegmentRange::new(element, word);
+        range.ordering = range.ordering();
+        relevance.score(0, &range, &[(element, word)])
+    }
+
+    #[test]
+    fn rare_words_count_for_more() {
+        let searcher = searcher_with(
+            &["grace mercy", "grace truth", "grace peace"],
+            &[],
+            Synonyms::default(),
+        );
+        // "grace" is in every transcript, and "mercy" in one
+        let relevance = Relevance::new(
+            [3, 1],
+            vec![vec![], vec![]],
+            &searcher.term_statistics,
+            &searcher.transcript_words,
+            &searcher.vocabulary,
+        );
+        let grace = score_at(&relevance, 0, 0);
+        let mercy = score_at(&relevance, 1, 1);
+        assert!(grace > 0.0);
+        assert!(mercy > grace, "{mercy} should be above {grace}");
+    }
+
+    #[test]
+    fn fuzzy_matches_count_for_less() {
+        let searcher = searcher_with(&["grace graze", "mercy truth"], &[], Synonyms::default());
+        // "graze" only matches "grace" by its spelling
+        let relevance = Relevance::new(
+            [1],
+            vec![vec!["graze".to_string()]],
+            &searcher.term_statistics,
+            &searcher.transcript_words,
+            &searcher.vocabulary,
+        );
+        let exact = score_at(&relevance, 0, 0);
+        let fuzzy = score_at(&relevance, 1, 0);
+        // both transcripts are as long as the average, so only the term frequencies differ
+        let saturated =
+            |frequency: f64| frequency * (TERM_SATURATION + 1.0) / (frequency + TERM_SATURATION);
+        let expected = saturated(FUZZY_WEIGHT) / saturated(1.0);
+        assert!((fuzzy / exact - expected).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::numbers::parse_number;
+use crate::searcher::normalize_word;
+
+/// A book of the Bible, as its number (for books such as "1 John"), its names and the abbreviations
+/// that are only recognized in written references such as "Rom 8:28"
+struct Book {
+    number: Option<u8>,
+    names: &'static [&'static str],
+    abbreviations: &'static [&'static str],
+}
+
+const fn book(names: &'static [&'static str], abbreviations: &'static [&'static str]) -> Book {
+    Book {
+        number: None,
+        names,
+        abbreviations,
+    }
+}
+
+const fn numbered(
+    number: u8,
+    names: &'static [&'static str],
+    abbreviations: &'static [&'static str],
+) -> Book {
+    Book {
+        number: Some(number),
+        names,
+        abbreviations,
+    }
+}
+
+/// In canonical order, where the first name (with the number) is how a book is written
+const BOOKS: [Book; 66] = [
+    book(&["genesis"], &["gen", "ge", "gn"]),
+    book(&["exodus"], &["exod", "exo", "ex"]),
+    book(&["leviticus"], &["lev", "le", "lv"]),
+    book(&["numbers"], &["num", "nu", "nm", "nb"]),
+    book(&["deuteronomy"], &["deut", "deu", "dt"]),
+    book(&["joshua"], &["josh", "jos", "jsh"]),
+    book(&["judges"], &["judg", "jdg", "jg", "jdgs"]),
+    book(&["ruth"], &["rth", "ru"]),
+    numbered(1, &["samuel"], &["sam", "sa", "sm"]),
+    numbered(2, &["samuel"], &["sam", "sa", "sm"]),
+    numbered(1, &["kings"], &["kgs", "ki", "kin"]),
+    numbered(2, &["kings"], &["kgs", "ki", "kin"]),
+    numbered(1, &["chronicles"], &["chron", "chr", "ch"]),
+    numbered(2, &["chronicles"], &["chron", "chr", "ch"]),
+    book(&["ezra"], &["ezr"]),
+    book(&["nehemiah"], &["neh", "ne"]),
+    book(&["esther"], &["esth", "est", "es"]),
+    book(&["job"], &["jb"]),
+    book(&["psalms", "psalm"], &["ps", "psa", "pss", "psm"]),
+    book(&["proverbs"], &["prov", "pro", "prv", "pr"]),
+    book(&["ecclesiastes"], &["eccl", "eccles", "ecc", "ec", "qoh"]),
+    book(
+        &[
+            "song of solomon",
+            "song of songs",
+            "songs of solomon",
+            "canticles",
+        ],
+        &["song", "sos", "ss"],
+    ),
+    book(&["isaiah"], &["isa", "is"]),
+    book(&["jeremiah"], &["jer", "je", "jr"]),
+    book(&["lamentations"], &["lam", "la"]),
+    book(&["ezekiel"], &["ezek", "eze", "ezk"]),
+    book(&["daniel"], &["dan", "da", "dn"]),
+    book(&["hosea"], &["hos", "ho"]),
+    book(&["joel"], &["jl"]),
+    book(&["amos"], &["am"]),
+    book(&["obadiah"], &["obad", "ob"]),
+    book(&["jonah"], &["jnh", "jon"]),
+    book(&["micah"], &["mic", "mc"]),
+    book(&["nahum"], &["nah", "na"]),
+    book(&["habakkuk"], &["hab", "hb"]),
+    book(&["zephaniah"], &["zeph", "zep", "zp"]),
+    book(&["haggai"], &["hag", "hg"]),
+    book(&["zechariah"], &["zech", "zec", "zc"]),
+    book(&["malachi"], &["mal", "ml"]),
+    book(&["matthew"], &["matt", "mat", "mt"]),
+    book(&["mark"], &["mrk", "mk", "mr"]),
+    book(&["luke"], &["luk", "lk"]),
+    book(&["john"], &["jhn", "jn", "joh"]),
+    book(&["acts"], &["act", "ac"]),
+    book(&["romans"], &["rom", "ro", "rm"]),
+    numbered(1, &["corinthians"], &["cor", "co"]),
+    numbered(2, &["corinthians"], &["cor", "co"]),
+    book(&["galatians"], &["gal", "ga"]),
+    book(&["ephesians"], &["eph", "ephes"]),
+    book(&["philippians"], &["phil", "php", "pp"]),
+    book(&["colossians"], &["col"]),
+    numbered(1, &["thessalonians"], &["thess", "thes", "th"]),
+    numbered(2, &["thessalonians"], &["thess", "thes", "th"]),
+    numbered(1, &["timothy"], &["tim", "ti"]),
+    numbered(2, &["timothy"], &["tim", "ti"]),
+    book(&["titus"], &["tit"]),
+    book(&["philemon"], &["philem", "phm", "pm"]),
+    book(&["hebrews"], &["heb"]),
+    book(&["james"], &["jas", "jm"]),
+    numbered(1, &["peter"], &["pet", "pe", "pt"]),
+    numbered(2, &["peter"], &["pet", "pe", "pt"]),
+    numbered(1, &["john"], &["jhn", "jn", "jo"]),
+    numbered(2, &["john"], &["jhn", "jn", "jo"]),
+    numbered(3, &["john"], &["jhn", "jn", "jo"]),
+    book(&["jude"], &["jud"]),
+    book(&["revelation", "revelations"], &["rev", "re"]),
+];
+
+/// How the number of a book such as "1 John" can be written or said
+fn number_prefixes(number: u8) -> &'static [&'static str] {
+    match number {
+        1 => &["1", "first", "1st", "i"],
+        2 => &["2", "second", "2nd", "ii"],
+        _ => &["3", "third", "3rd", "iii"],
+    }
+}
+
+/// One way of naming a book, as normalized words
+struct BookAlias {
+    words: Vec<String>,
+    book: usize,
+    is_abbreviation: bool,
+}
+
+/// First word -> every alias starting with it, longest first
+static BOOK_ALIASES: Lazy<HashMap<String, Vec<BookAlias>>> = Lazy::new(|| {
+    let mut aliases: HashMap<String, Vec<BookAlias>> = HashMap::new();
+    for (idx, book) in BOOKS.iter().enumerate() {
+        let names = book.names.iter().map(|name| (name, false));
+        let abbreviations = book.abbreviations.iter().map(|name| (name, true));
+        for (name, is_abbreviation) in names.chain(abbreviations) {
+            let name: Vec<String> = name.split_whitespace().map(String::from).collect();
+            let mut variants = vec![];
+            match book.number {
+                Some(number) => {
+                    for prefix in number_prefixes(number) {
+                        variants.push([vec![prefix.to_string()], name.clone()].concat());
+                    }
+                    // "1John"
+                    let mut fused = name.clone();
+                    fused[0] = format!("{number}{}", fused[0]);
+                    variants.push(fused);
+                }
+                None => variants.push(name),
+            }
+            for words in variants {
+                aliases
+                    .entry(words[0].clone())
+                    .or_default()
+                    .push(BookAlias {
+                        words,
+                        book: idx,
+                        is_abbreviation,
+                    });
+            }
+        }
+    }
+    for aliases in aliases.values_mut() {
+        aliases.sort_by_key(|alias| std::cmp::Reverse(alias.words.len()));
+    }
+    aliases
+});
+
+/// `8:28` or `8:28-30`, with any punctuation after it
+static WRITTEN_REFERENCE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d+):(\d+)(?:[-–—](\d+))?\W*$").unwrap());
+/// `28-30`, with any punctuation after it
+static WRITTEN_RANGE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\d+)[-–—](\d+)\W*$").unwrap());
+
+/// A passage of the Bible, from a whole book down to a range of verses
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Passage {
+    /// The index of the book in canonical order
+    book: usize,
+    /// [`None`] for the whole book
+    pub chapter: Option<u32>,
+    /// The first and last verse, or [`None`] for the whole chapter
+    pub verses: Option<(u32, u32)>,
+}
+
+impl Display for Passage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let book = &BOOKS[self.book];
+        if let Some(number) = book.number {
+            write!(f, "{number} ")?;
+        }
+        let name: Vec<String> = book.names[0]
+            .split_whitespace()
+            .map(|word| match word {
+                "of" => word.to_string(),
+                _ => word[..1].to_ascii_uppercase() + &word[1..],
+            })
+            .collect();
+        write!(f, "{}", name.join(" "))?;
+        if let Some(chapter) = self.chapter {
+            write!(f, " {chapter}")?;
+        }
+        match self.verses {
+            Some((first, last)) if first == last => write!(f, ":{first}"),
+            Some((first, last)) => write!(f, ":{first}-{last}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Passage {
+    /**
+    Parse a written reference, such as `Rom 8:28`, `Romans 8:28-30`, `1 John 3` or `Psalms`
+    - Abbreviations are recognized here in every form, unlike in transcripts (see [`find_citations`])
+    */
+    pub fn parse(reference: &str) -> Result<Self, String> {
+        let raw: Vec<&str> = reference.split_whitespace().collect();
+        let words: Vec<String> = raw.iter().map(|word| normalize_word(word)).collect();
+        let Some(alias) = match_book(&words) else {
+            return Err(format!(
+                "{reference:?} doesn't start with a book of the Bible"
+            ));
+        };
+        let after_book = alias.words.len();
+        let (passage, length) = if after_book == raw.len() {
+            (
+                Self {
+                    book: alias.book,
+                    chapter: None,
+                    verses: None,
+                },
+                0,
+            )
+        } else {
+            match_chapter(&raw[after_book..], &words[after_book..], alias.book, false).ok_or_else(
+                || format!("Expected a reference such as Romans 8:28, not {reference:?}"),
+            )?
+        };
+        if after_book + length < raw.len() {
+            return Err(format!(
+                "Expected a reference such as Romans 8:28, not {reference:?}"
+            ));
+        }
+        if passage.verses.is_some_and(|(first, last)| first > last) {
+            return Err(format!("The verses of {reference:?} end before they start"));
+        }
+        Ok(passage)
+    }
+
+    /// Whether the two passages have any verse in common, where a whole chapter or book has every
+    /// verse in it
+    pub fn overlaps(&self, other: &Passage) -> bool {
+        let chapters_overlap = match (self.chapter, other.chapter) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        };
+        let verses_overlap = match (self.verses, other.verses) {
+            (Some((a_first, a_last)), Some((b_first, b_last))) => {
+                a_first <= b_last && b_first <= a_last
+            }
+            _ => true,
+        };
+        self.book == other.book && chapters_overlap && verses_overlap
+    }
+}
+
+/// The longest name of a book that `words` start with
+fn match_book(words: &[String]) -> Option<&'static BookAlias> {
+    BOOK_ALIASES
+        .get(words.first()?)?
+        .iter()
+        .find(|alias| words.starts_with(&alias.words))
+}
+
+/**
+The chapter and verses that follow the name of a book, and how many words they are
+- Written, as in "8:28", "8:28-30" or "8" (after an abbreviation, only the first two)
+- Spoken, as in "chapter eight", "chapter eight verse twenty eight", "eight verses twenty eight
+  through thirty" or "8 verse 28", where a spoken chapter needs "chapter" before it or a verse after
+  it, since "Mark two things" isn't a reference
+- `raw` are the words as written, and `words` are them normalized
+*/
+fn match_chapter(
+    raw: &[&str],
+    words: &[String],
+    book: usize,
+    is_abbreviation: bool,
+) -> Option<(Passage, usize)> {
+    let passage = |chapter: u32, verses: Option<(u32, u32)>| Passage {
+        book,
+        chapter: Some(chapter),
+        verses,
+    };
+    let has_chapter_word = words.first().is_some_and(|word| word == "chapter");
+    let at = usize::from(has_chapter_word);
+    if let Some(captures) = raw
+        .get(at)
+        .and_then(|word| WRITTEN_REFERENCE.captures(word))
+    {
+        let number = |idx: usize| captures.get(idx)?.as_str().parse::<u32>().ok();
+        let first_verse = number(2)?;
+        let verses = (first_verse, number(3).unwrap_or(first_verse));
+        return Some((passage(number(1)?, Some(verses)), at + 1));
+    }
+    if is_abbreviation {
+        return None;
+    }
+
+    let (chapter, length) = parse_number(&words[at..])?;
+    let is_written = words[at].chars().all(|c| c.is_ascii_digit());
+    let mut end = at + length;
+    let verses = match words.get(end).map(String::as_str) {
+        Some("verse" | "verses") => {
+            let (first, length) = parse_number(&words[end + 1..])?;
+            end += 1 + length;
+            let mut last = first;
+            let is_range = match words.get(end).map(String::as_str) {
+                Some("through" | "thru" | "to") => true,
+                // a dash on its own is empty once normalized
+                Some("") => raw[end].trim().starts_with(['-', '–', '—']),
+                _ => false,
+            };
+            if let Some((to, length)) = is_range.then(|| parse_number(&words[end + 1..])).flatten()
+            {
+                last = to;
+                end += 1 + length;
+            }
+            Some((first, last))
+        }
+        _ => None,
+    };
+    if !has_chapter_word && !is_written && verses.is_none() {
+        return None;
+    }
+    // "verses 28-30" is normalized to "2830"
+    if let Some(captures) = verses
+        .is_some()
+        .then(|| WRITTEN_RANGE.captures(raw[end - 1]))
+        .flatten()
+    {
+        let number = |idx: usize| captures.get(idx)?.as_str().parse::<u32>().ok();
+        return Some((passage(chapter, Some((number(1)?, number(2)?))), end));
+    }
+    Some((passage(chapter, verses), end))
+}
+
+/// Where a passage is cited in a transcript
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Citation {
+    /// The index of the first word of the reference
+    pub start: usize,
+    /// How many words the reference is
+    pub length: usize,
+    pub passage: Passage,
+}
+
+/// Whether a word as written is capitalized (such as "Is") or ends with a period (such as "is."), as
+/// the abbreviation of a book is when it is written out
+fn looks_abbreviated(raw: &str) -> bool {
+    raw.ends_with('.')
+        || raw
+            .chars()
+            .find(|c| c.is_alphabetic())
+            .is_some_and(char::is_uppercase)
+}
+
+/**
+Every scripture reference in a transcript, written (such as "Romans 8:28" or "Rom 8:28-30") or spoken
+(such as "Romans chapter eight verse twenty eight")
+- A book's name without a chapter after it isn't a reference, since many are also names or words
+  (such as "John" or "acts")
+- An abbreviation has to be capitalized or end with a period (see [`looks_abbreviated`]), so "the
+  service is 5:30" isn't Isaiah 5:30
+*/
+pub fn find_citations<'a>(raw: impl IntoIterator<Item = &'a str>) -> Vec<Citation> {
+    let raw: Vec<&str> = raw.into_iter().collect();
+    let words: Vec<String> = raw.iter().map(|word| normalize_word(word)).collect();
+    let mut citations = vec![];
+    let mut idx = 0;
+    while idx < words.len() {
+        let citation = match_book(&words[idx..]).and_then(|alias| {
+            let after_book = idx + alias.words.len();
+            if alias.is_abbreviation && !looks_abbreviated(raw[after_book - 1]) {
+                return None;
+            }
+            let (passage, length) = match_chapter(
+                &raw[after_book..],
+                &words[after_book..],
+                alias.book,
+                alias.is_abbreviation,
+            )?;
+            Some(Citation {
+                start: idx,
+                length: alias.words.len() + length,
+                passage,
+            })
+        });
+        match citation {
+            Some(citation) => {
+                idx += citation.length;
+                citations.push(citation);
+            }
+            None => idx += 1,
+        }
+    }
+    citations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn citations(text: &str) -> Vec<String> {
+        find_citations(text.split_whitespace())
+            .iter()
+            .map(|citation| citation.passage.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parse_written_references() {
+        let passage = Passage::parse("Rom 8:28-30").expect("It parses");
+        assert_eq!(passage.to_string(), "Romans 8:28-30");
+        assert_eq!(passage.chapter, Some(8));
+        assert_eq!(passage.verses, Some((28, 30)));
+        assert_eq!(Passage::parse("1 John 3").unwrap().to_string(), "1 John 3");
+        assert_eq!(
+            Passage::parse("song of solomon").unwrap().to_string(),
+            "Song of Solomon"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Passage::parse("Hezekiah 3:16").is_err());
+        assert!(Passage::parse("Romans eight and more").is_err());
+        assert!(Passage::parse("Romans 8:30-28").is_err());
+    }
+
+    #[test]
+    fn spoken_references() {
+        assert_eq!(
+            citations("Romans chapter eight verse twenty eight says"),
+            vec!["Romans 8:28"]
+        );
+        assert_eq!(
+            citations("turn to first John three verses ten through twelve"),
+            vec!["1 John 3:10-12"]
+        );
+        assert_eq!(citations("Mark two things down"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn written_references() {
+        assert_eq!(citations("read Rom 8:28-30 today"), vec!["Romans 8:28-30"]);
+        assert_eq!(citations("and John 3:16."), vec!["John 3:16"]);
+        assert_eq!(citations("see Is. 5:30"), vec!["Isaiah 5:30"]);
+    }
+
+    #[test]
+    fn lowercase_abbreviations_are_words() {
+        assert_eq!(citations("the service is 5:30"), Vec::<String>::new());
+        assert_eq!(citations("Is 5:30 right"), vec!["Isaiah 5:30"]);
+    }
+
+    #[test]
+    fn passages_overlap() {
+        let romans = Passage::parse("Romans 8").unwrap();
+        let verse = Passage::parse("Romans 8:28").unwrap();
+        assert!(romans.overlaps(&verse));
+        assert!(!verse.overlaps(&Passage::parse("Romans 8:1").unwrap()));
+        assert!(!verse.overlaps(&Passage::parse("Romans 9:28").unwrap()));
+    }
+}
@@ -1,20 +1,70 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use glob::{MatchOptions, Pattern};
+use rocket::FromForm;
 use rocket::{
-    form::validate::{Contains, Len},
+    form::validate::Contains,
     futures::{stream::iter, StreamExt},
 };
 use serde::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
 use crate::{
-    hsk_file::{HskFile, Word},
-    merge::{merge_special, WordSegmentRange},
-    utils::find_all_extended_words,
+    app_config::APP_EXT,
+    fuzzy::{allowed_distance, BkTree, FuzzyMode},
+    hsk_file::{
+        compare_dates, index_tokens, is_valid_date, HskFile, Provenance, TranscriptMetadata, Word,
+    },
+    merge::{compare_ranges, merge_required, AllowedRange, TimedRange, WordSegmentRange},
+    numbers::number_token,
+    phonetic::{phonetic_key, MIN_PHONETIC_LENGTH},
+    postings::Postings,
+    query::{
+        is_boolean_query, parse_boolean_query, parse_query, Presence, QueryExpression, QueryTerm,
+        WILDCARD,
+    },
+    relevance::{Relevance, TermStatistics},
+    scripture::{find_citations, Citation, Passage},
+    stemmer::Stemmer,
+    synonyms::Synonyms,
+    vocabulary::{IndexedWord, TokenId, Vocabulary},
+    word_id::{WordId, WordIdSet},
     CONFIG,
 };
 
 pub type Map<K, V> = BTreeMap<K, V>;
+/// A map whose copies share everything that isn't changed afterwards, so that a [`Searcher`] can be
+/// copied and updated without copying the whole index (see [`Searcher::reloaded`])
+pub type SharedMap<K, V> = im::OrdMap<K, V>;
+
+pub fn is_transcript_file(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext == APP_EXT)
+}
+
+/**
+How a transcript is identified: its path relative to the directory it was loaded from, without extension
+- `roots` are from [`AppConfig::transcript_roots`](crate::app_config::AppConfig::transcript_roots), and
+  the most specific one containing `path` is used
+*/
+pub fn transcript_path(roots: &[(String, PathBuf)], path: &Path) -> Option<String> {
+    let (prefix, root) = roots
+        .iter()
+        .filter(|(_, root)| path.starts_with(root))
+        .max_by_key(|(_, root)| root.components().count())?;
+    let mut relative_path = Path::new(prefix).join(path.strip_prefix(root).ok()?);
+    relative_path.set_extension("");
+    Some(relative_path.to_string_lossy().to_string())
+}
+
+/// Whether `transcript_path` is `prefix` or is nested under it
+pub fn is_in_prefix(transcript_path: &str, prefix: &str) -> bool {
+    prefix.is_empty()
+        || transcript_path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
 
 pub fn normalize_word(word: &str) -> String {
     word.chars()
@@ -23,146 +73,1063 @@ pub fn normalize_word(word: &str) -> String {
         .collect()
 }
 
+/// A stop word (or a word to keep) as [`parse_query`] gives it, with a number replaced by its digits,
+/// so that the stop word "one" is still found in the query `one god`
+fn query_word(word: &str) -> String {
+    let word = normalize_word(word);
+    match number_token(std::slice::from_ref(&word)) {
+        Some((token, 1)) => token,
+        _ => word,
+    }
+}
+
 pub type WordIndices = Vec<usize>;
 pub type TranscriptId = usize;
-pub type TranscriptWordIndices = (TranscriptId, WordIndices);
+/**
+Transcripts without an id of their own are given ids from here up while they are loaded
+- [`claim_transcript_id`](crate::hsk_file::claim_transcript_id) counts up from 0, so saved ids never
+  reach this far and the two can't collide
+- It stays below 2^53, so that JavaScript can still read these ids exactly
+*/
+pub const UNSAVED_IDS_START: TranscriptId = 1 << 48;
+pub type TranscriptWordIndices = (TranscriptId, Postings);
 
 pub type WordToWordIndices = Map<String, WordIndices>;
 
-pub type WordToTranscriptAndWordIndicesMap = Map<String, Vec<TranscriptWordIndices>>;
+/// Each word's postings are only copied when a transcript with it changes
+pub type WordToTranscriptAndWordIndicesMap = SharedMap<TokenId, Arc<Vec<TranscriptWordIndices>>>;
+/// Transcript id -> its words, which are never changed (only replaced)
+pub type TranscriptWords = SharedMap<TranscriptId, Arc<Vec<IndexedWord>>>;
 pub type OrganizedSearchResult = Map<usize, Map<usize, Vec<QueryResult>>>;
+/// Each transcript's segments from best to worst, in transcript id order
+pub type RankedSegments = Vec<(TranscriptId, WordSegmentRange)>;
+
+/// Below this, a shard isn't worth its own thread
+const MIN_TRANSCRIPTS_PER_SHARD: usize = 32;
+
+/// Where a match of a query term starts, and how many words it is (more than one for a phrase)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Occurrence {
+    start: usize,
+    length: usize,
+}
+
+/// Sort each transcript's occurrences by where they start, keeping only the longest at each start
+fn sort_occurrences(occurrences: &mut Map<TranscriptId, Vec<Occurrence>>) {
+    for occurrences in occurrences.values_mut() {
+        occurrences.sort_by(|a, b| a.start.cmp(&b.start).then(b.length.cmp(&a.length)));
+        occurrences.dedup_by_key(|occurrence| occurrence.start);
+    }
+}
+
+/// Where one query term is found, in every transcript that has it
+enum TermFindings<'a> {
+    Word(&'a [TranscriptWordIndices]),
+    /// For phrases and terms that match more than one word (see [`sort_occurrences`])
+    Occurrences(Map<TranscriptId, Vec<Occurrence>>),
+}
+
+impl TermFindings<'_> {
+    fn iter(&self) -> Box<dyn Iterator<Item = (TranscriptId, TermIndices<'_>)> + '_> {
+        match self {
+            Self::Word(findings) => Box::new(
+                findings
+                    .iter()
+                    .map(|(transcript_id, postings)| (*transcript_id, TermIndices::Word(postings))),
+            ),
+            Self::Occurrences(occurrences) => Box::new(
+                occurrences
+                    .iter()
+                    .filter(|(_, occurrences)| !occurrences.is_empty())
+                    .map(|(transcript_id, occurrences)| {
+                        (*transcript_id, TermIndices::Occurrences(occurrences))
+                    }),
+            ),
+        }
+    }
+
+    /// The most words that a match of the term is
+    fn max_length(&self) -> usize {
+        match self {
+            Self::Word(_) => 1,
+            Self::Occurrences(occurrences) => occurrences
+                .values()
+                .flatten()
+                .map(|occurrence| occurrence.length)
+                .max()
+                .unwrap_or(1),
+        }
+    }
+
+    /// How many transcripts have the term
+    fn document_frequency(&self) -> usize {
+        self.iter().count()
+    }
+}
+
+/// Where one query term is found in one transcript
+#[derive(Copy, Clone, Debug)]
+enum TermIndices<'a> {
+    Word(&'a Postings),
+    Occurrences(&'a [Occurrence]),
+}
+
+impl<'a> TermIndices<'a> {
+    /// Where each match starts
+    fn iter(&self) -> Box<dyn Iterator<Item = usize> + Send + 'a> {
+        match *self {
+            Self::Word(postings) => Box::new(postings.iter()),
+            Self::Occurrences(occurrences) => {
+                Box::new(occurrences.iter().map(|occurrence| occurrence.start))
+            }
+        }
+    }
+
+    /// How many words the match starting at `start` is
+    fn length_at(&self, start: usize) -> usize {
+        match self {
+            Self::Word(_) => 1,
+            Self::Occurrences(occurrences) => occurrences
+                .binary_search_by_key(&start, |occurrence| occurrence.start)
+                .map_or(1, |idx| occurrences[idx].length),
+        }
+    }
+}
+
+/// The word indices of a query's terms in one transcript
+#[derive(Clone, Debug)]
+struct TranscriptMatches<'a> {
+    /// In the same order as the query's (not excluded) terms, with [`None`] for the terms that the
+    /// transcript doesn't have
+    terms: Vec<Option<TermIndices<'a>>>,
+    /// The excluded terms that the transcript has
+    excluded: Vec<TermIndices<'a>>,
+}
+
+impl<'a> TranscriptMatches<'a> {
+    fn new(term_count: usize) -> Self {
+        Self {
+            terms: vec![None; term_count],
+            excluded: vec![],
+        }
+    }
+
+    /// - `unmerged`: the terms to leave out (as if the transcript didn't have them)
+    fn term_indices(
+        &self,
+        unmerged: &WordIdSet,
+    ) -> Vec<Box<dyn Iterator<Item = usize> + Send + 'a>> {
+        self.terms
+            .iter()
+            .enumerate()
+            .map(|(idx, indices)| match indices {
+                Some(indices) if !unmerged.contains(WordId::from_index(idx)) => indices.iter(),
+                _ => Box::new(std::iter::empty()),
+            })
+            .collect()
+    }
+
+    /**
+    Where the matches that make a [`QueryExpression`] true start within `bounds` (the first and last
+    word index of a segment)
+    - [`None`] when it isn't true there
+    - A [`QueryExpression::Not`] has to be false in all of `window` (see [`surrounding_window`]), not
+      just in `bounds`, and is true without any matches, so it never satisfies a
+      [`QueryExpression::Near`]
+    */
+    fn expression_starts(
+        &self,
+        expression: &QueryExpression,
+        bounds: (usize, usize),
+        window: (usize, usize),
+    ) -> Option<Vec<usize>> {
+        let (min, max) = bounds;
+        match expression {
+            QueryExpression::Term(idx) => {
+                let indices = self.terms[*idx]?;
+                let starts: Vec<usize> = indices
+                    .iter()
+                    .take_while(|start| *start <= max)
+                    .filter(|start| start + indices.length_at(*start) > min)
+                    .collect();
+                (!starts.is_empty()).then_some(starts)
+            }
+            QueryExpression::And(expressions) => {
+                let starts = expressions
+                    .iter()
+                    .map(|expression| self.expression_starts(expression, bounds, window))
+                    .collect::<Option<Vec<Vec<usize>>>>()?;
+                Some(starts.concat())
+            }
+            QueryExpression::Or(expressions) => {
+                let starts: Vec<Vec<usize>> = expressions
+                    .iter()
+                    .filter_map(|expression| self.expression_starts(expression, bounds, window))
+                    .collect();
+                (!starts.is_empty()).then_some(starts.concat())
+            }
+            QueryExpression::Not(expression) => {
+                match self.expression_starts(expression, window, window) {
+                    Some(_) => None,
+                    None => Some(vec![]),
+                }
+            }
+            QueryExpression::Near(left, right, distance) => {
+                let left = self.expression_starts(left, bounds, window)?;
+                let right = self.expression_starts(right, bounds, window)?;
+                let is_near = |start: &usize, others: &[usize]| {
+                    others
+                        .iter()
+                        .any(|other| start.abs_diff(*other) <= *distance)
+                };
+                let starts: Vec<usize> = left
+                    .iter()
+                    .filter(|start| is_near(start, &right))
+                    .chain(right.iter().filter(|start| is_near(start, &left)))
+                    .copied()
+                    .collect();
+                (!starts.is_empty()).then_some(starts)
+            }
+        }
+    }
+
+    /// How many words the match of a term that starts at `start` is
+    fn match_length(&self, word_id: WordId, start: usize) -> usize {
+        self.terms[word_id.to_index()].map_or(1, |indices| indices.length_at(start))
+    }
+
+    /// Whether a match of an excluded term overlaps the range
+    fn excludes(&self, range: &WordSegmentRange) -> bool {
+        self.excluded.iter().any(|indices| {
+            indices
+                .iter()
+                .take_while(|start| *start <= range.max)
+                .any(|start| start + indices.length_at(start) > range.min)
+        })
+    }
+}
+
+/// How [`group_segments`] finds and scores the segments of a query
+struct SegmentSearch<'a> {
+    /// In words
+    allowed_range: usize,
+    /// Measure the allowed range in seconds instead (see [`TimedRange`])
+    window_seconds: Option<f64>,
+    transcript_words: &'a TranscriptWords,
+    /// The required terms
+    required: WordIdSet,
+    /// The terms that are only used to rule segments out (see
+    /// [`BooleanQuery::negated_terms`](crate::query::BooleanQuery::negated_terms))
+    negated: WordIdSet,
+    /// Only keep the segments where this is true, for a [`BooleanQuery`](crate::query::BooleanQuery)
+    expression: Option<&'a QueryExpression>,
+    /// Whether any term can match more than one word
+    has_phrases: bool,
+    relevance: Relevance<'a>,
+}
 
+/**
+The first and last word index that are within `allowed_range` of either end of `range`, where
+nothing after `NOT` can be for the segment to match
+- Otherwise `faith NOT works` would match "faith works", as "works" is just outside the segment of
+  "faith"
+*/
+fn surrounding_window(
+    range: &WordSegmentRange,
+    allowed_range: &impl AllowedRange,
+    word_count: usize,
+) -> (usize, usize) {
+    let mut start = range.min;
+    while start > 0 && allowed_range.compare(start - 1, range.min).is_le() {
+        start -= 1;
+    }
+    let mut end = range.max;
+    while end + 1 < word_count && allowed_range.compare(range.max, end + 1).is_le() {
+        end += 1;
+    }
+    (start, end)
+}
+
+/// The segments that [`merge_required`] finds in one transcript, with their [`surrounding_window`]
+fn transcript_segments(
+    matches: &TranscriptMatches,
+    allowed_range: &impl AllowedRange,
+    search: &SegmentSearch,
+    word_count: usize,
+) -> Vec<(WordSegmentRange, (usize, usize))> {
+    merge_required(
+        matches.term_indices(&search.negated),
+        allowed_range,
+        &search.required,
+    )
+    .into_iter()
+    .map(|sr| {
+        let window = surrounding_window(&sr, allowed_range, word_count);
+        (sr, window)
+    })
+    .collect()
+}
+
+/// Find the segments of every transcript with [`merge_required`] and score them
+fn group_segments<'a>(
+    transcript_indices: impl IntoIterator<Item = (TranscriptId, TranscriptMatches<'a>)>,
+    search: &SegmentSearch,
+) -> RankedSegments {
+    let mut results = RankedSegments::default();
+
+    for (transcript_id, matches) in transcript_indices {
+        let transcript_words = search
+            .transcript_words
+            .get(&transcript_id)
+            .map_or(&[][..], |words| words.as_slice());
+        let word_segment_ranges = match search.window_seconds {
+            Some(seconds) => {
+                let allowed_range = TimedRange {
+                    seconds,
+                    words: search.allowed_range,
+                    transcript_words,
+                };
+                transcript_segments(&matches, &allowed_range, search, transcript_words.len())
+            }
+            None => transcript_segments(
+                &matches,
+                &search.allowed_range,
+                search,
+                transcript_words.len(),
+            ),
+        };
+        let word_segment_ranges = word_segment_ranges
+            .into_iter()
+            .filter(|(sr, _)| !matches.excludes(sr))
+            .filter(|(sr, window)| {
+                search.expression.is_none_or(|expression| {
+                    matches
+                        .expression_starts(expression, (sr.min, sr.max), *window)
+                        .is_some()
+                })
+            })
+            .map(|(mut sr, _)| {
+                let starts: Vec<(usize, WordId)> = sr
+                    .elements
+                    .iter()
+                    .copied()
+                    .zip(sr.words.iter().copied())
+                    .collect();
+                if search.has_phrases {
+                    sr.expand_phrases(|element, word_id| matches.match_length(word_id, element));
+                    sr.ordering = sr.ordering();
+                }
+                sr.score = search.relevance.score(transcript_id, &sr, &starts);
+                (transcript_id, sr)
+            });
+        results.extend(word_segment_ranges);
+    }
+    results
+}
+
+/**
+[`group_segments`], but with the transcripts split into shards that are each run on their own thread
+- Shards are consecutive runs of transcript ids and their segments are appended in shard order, so
+  the results are identical to running [`group_segments`] on all of them at once
+*/
+fn group_segments_in_parallel(
+    transcript_indices: Map<TranscriptId, TranscriptMatches>,
+    search: &SegmentSearch,
+) -> RankedSegments {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let shard_size = transcript_indices
+        .len()
+        .div_ceil(threads)
+        .max(MIN_TRANSCRIPTS_PER_SHARD);
+    if transcript_indices.len() <= shard_size {
+        return group_segments(transcript_indices, search);
+    }
+    let transcript_indices: Vec<_> = transcript_indices.into_iter().collect();
+    let shards: Vec<RankedSegments> = std::thread::scope(|scope| {
+        let handles: Vec<_> = transcript_indices
+            .chunks(shard_size)
+            .map(|shard| scope.spawn(move || group_segments(shard.iter().cloned(), search)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("Search shard panicked"))
+            .collect()
+    });
+
+    shards.into_iter().flatten().collect()
+}
+
+/**
+The first and last word indices to give for a segment, including its context
+- `context_seconds`: the words that start within this many seconds of the segment, stopping at the
+  first word without a timestamp (or `context` words, when the segment's own first or last word
+  isn't timed)
+*/
+fn context_bounds(
+    transcript_words: &[IndexedWord],
+    range: &WordSegmentRange,
+    context: usize,
+    context_seconds: Option<f64>,
+) -> (usize, usize) {
+    let last = transcript_words.len().saturating_sub(1);
+    let mut start = range.min.saturating_sub(context);
+    let mut end = std::cmp::min(range.max + context, last);
+    let Some(seconds) = context_seconds else {
+        return (start, end);
+    };
+    let time = |idx: usize| {
+        let word = transcript_words.get(idx)?;
+        word.start.or(word.end)
+    };
+    if let Some(first_time) = time(range.min) {
+        start = range.min;
+        while start > 0 && time(start - 1).is_some_and(|time| first_time - time <= seconds) {
+            start -= 1;
+        }
+    }
+    if let Some(last_time) = time(range.max) {
+        end = range.max;
+        while end < last && time(end + 1).is_some_and(|time| time - last_time <= seconds) {
+            end += 1;
+        }
+    }
+    (start, end)
+}
+
+#[derive(Clone)]
 pub struct Searcher {
-    pub transcript_paths: Vec<String>,
-    // transcript id -> Word
-    pub transcript_words: Map<TranscriptId, Vec<Word>>,
-    // word to transcript id
+    // transcript id -> path relative to the data directory (without extension)
+    pub transcript_paths: SharedMap<TranscriptId, String>,
+    // path relative to the data directory -> transcript id (the reverse of `transcript_paths`)
+    pub transcript_ids: SharedMap<String, TranscriptId>,
+    // transcript id -> Word (with its text in `vocabulary`)
+    pub transcript_words: TranscriptWords,
+    // every distinct word in `transcript_words` and in `map`
+    pub vocabulary: Vocabulary,
+    // transcript id -> how it was converted (if it was recorded)
+    pub transcript_provenance: SharedMap<TranscriptId, Provenance>,
+    // transcript id -> its date, speaker, series and tags
+    pub transcript_metadata: SharedMap<TranscriptId, TranscriptMetadata>,
+    // transcript id -> every scripture reference in it
+    pub citations: SharedMap<TranscriptId, Arc<Vec<Citation>>>,
+    // indexed word (its id in `vocabulary`) -> where it is in each transcript that has it
     pub map: WordToTranscriptAndWordIndicesMap,
-    pub all_words: Vec<String>,
+    // the word lists below are updated as words are added to and removed from `map`
+    // stem -> every indexed word with that stem
+    pub stems: SharedMap<String, Vec<TokenId>>,
+    pub stemmer: Stemmer,
+    // every indexed word, for finding words spelled like a query word
+    pub fuzzy_words: BkTree,
+    // phonetic key -> every indexed word (that is long enough) that sounds like it
+    pub phonetic_words: SharedMap<String, Vec<TokenId>>,
+    pub term_statistics: TermStatistics,
     pub stop_words: Vec<String>,
+    pub synonyms: Synonyms,
+    // collection name -> its own stop words (if it has them)
+    pub collection_stop_words: Map<String, Vec<String>>,
 }
 
 impl Searcher {
     pub fn load() -> Self {
-        let data_dir = CONFIG.data_dir();
-        let mut transcript_id: usize = 0;
-        let mut transcript_paths = Vec::new();
-        let mut transcript_words: Map<TranscriptId, Vec<Word>> = Map::new();
-        let mut map: WordToTranscriptAndWordIndicesMap = Map::new();
-        for entry in WalkDir::new(&data_dir).into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path();
-            if path.is_file() && path.extension().is_some_and(|ext| ext == "hsk") {
-                if let Ok(file) = HskFile::read(path) {
-                    let mut relative_path = path.strip_prefix(&data_dir).unwrap().to_path_buf();
-                    relative_path.set_extension("");
-                    transcript_paths.push(relative_path.to_string_lossy().to_string());
-                    transcript_words.insert(transcript_id, file.words);
-                    file.word_index_map.into_iter().for_each(|(word, indices)| {
-                        if let Some(existing_entry) = map.get_mut(&word) {
-                            existing_entry.push((transcript_id, indices));
-                        } else {
-                            _ = map.insert(word.clone(), vec![(transcript_id, indices)]);
-                        }
-                    });
-                    transcript_id += 1;
+        Self::load_with_ids(&SharedMap::new())
+    }
+
+    /// A new searcher with every transcript read from disk again, which gives transcripts without
+    /// an id of their own the ids they have in this one
+    pub fn reloaded_all(&self) -> Self {
+        Self::load_with_ids(&self.transcript_ids)
+    }
+
+    fn load_with_ids(previous_ids: &SharedMap<String, TranscriptId>) -> Self {
+        let roots = CONFIG.transcript_roots();
+        let stop_words = CONFIG.stop_words().unwrap_or_default();
+        let synonyms = CONFIG.synonyms().unwrap_or_default();
+        let collection_stop_words = CONFIG
+            .collections
+            .keys()
+            .filter_map(|name| Some((name.clone(), CONFIG.collection_stop_words(name)?)))
+            .collect();
+        let mut searcher = Self::new(
+            CONFIG.stemmer(),
+            stop_words,
+            synonyms,
+            collection_stop_words,
+        );
+        // files that already have an id claim it first, so that new ids never collide with them
+        let mut without_id = vec![];
+        for (_, root) in roots.iter() {
+            let walker = WalkDir::new(root).sort_by_file_name();
+            for entry in walker.into_iter().filter_map(|e| e.ok()) {
+                let path = entry.path();
+                if !is_transcript_file(path) {
+                    continue;
+                }
+                let (Some(transcript), Ok(mut file)) =
+                    (transcript_path(&roots, path), HskFile::read(path))
+                else {
+                    continue;
+                };
+                // a separate collection directory nested in another root is only loaded once
+                if searcher.get_transcript_id(&transcript).is_some() {
+                    continue;
+                }
+                let has_own_id = file
+                    .id
+                    .is_some_and(|id| !searcher.transcript_paths.contains_key(&id));
+                if has_own_id {
+                    searcher.insert_transcript(transcript, file);
+                } else {
+                    file.id = previous_ids.get(&transcript).copied();
+                    without_id.push((transcript, file));
                 }
             }
         }
-        let stop_words = match CONFIG.stop_words() {
-            Some(stop_words) => stop_words,
-            None => vec![],
-        };
-        let all_words: Vec<String> = map.keys().map(|key| key.to_string()).collect();
+        if !without_id.is_empty() {
+            println!(
+                "{} transcripts have no id of their own (run `migrate` to save one to them)",
+                without_id.len()
+            );
+        }
+        // the ids they had before go first, so that new ones can't take them
+        without_id.sort_by_key(|(_, file)| file.id.is_none());
+        for (transcript, file) in without_id {
+            searcher.insert_transcript(transcript, file);
+        }
+        searcher.update_term_statistics();
+        searcher
+    }
+
+    /// A searcher without any transcripts (see [`Searcher::insert_transcript`])
+    pub fn new(
+        stemmer: Stemmer,
+        stop_words: Vec<String>,
+        synonyms: Synonyms,
+        collection_stop_words: Map<String, Vec<String>>,
+    ) -> Self {
         Self {
-            transcript_paths,
-            transcript_words,
-            map,
-            all_words,
+            transcript_paths: SharedMap::new(),
+            transcript_ids: SharedMap::new(),
+            transcript_words: SharedMap::new(),
+            vocabulary: Vocabulary::default(),
+            transcript_provenance: SharedMap::new(),
+            transcript_metadata: SharedMap::new(),
+            citations: SharedMap::new(),
+            map: SharedMap::new(),
+            stems: SharedMap::new(),
+            stemmer,
+            fuzzy_words: BkTree::default(),
+            phonetic_words: SharedMap::new(),
+            term_statistics: TermStatistics::default(),
             stop_words,
+            synonyms,
+            collection_stop_words,
+        }
+    }
+
+    /**
+    A copy of this searcher with the given `.hsk` files re-read from disk
+    - The copy shares everything that the files don't change with this searcher (see [`SharedMap`])
+    - Files that no longer exist (or can't be read) are removed from the index
+    - Paths outside of the data directories or without the `.hsk` extension are ignored
+    */
+    pub fn reloaded(&self, changed_paths: &[PathBuf]) -> Self {
+        let roots = CONFIG.transcript_roots();
+        let mut searcher = self.clone();
+        let (removed, changed): (Vec<_>, Vec<_>) = changed_paths
+            .iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == APP_EXT))
+            .filter_map(|path| Some((transcript_path(&roots, path)?, path)))
+            .map(|(transcript, path)| (transcript, path, HskFile::read(path).ok()))
+            .partition(|(_, path, file)| file.is_none() || !path.is_file());
+        // remove first so that a moved file can keep its id
+        for (transcript, _, _) in removed {
+            _ = searcher.remove_transcript(&transcript);
+        }
+        for (transcript, _, file) in changed {
+            searcher.insert_transcript(transcript, file.expect("Already verified"));
         }
+        searcher.update_term_statistics();
+        searcher
     }
 
-    fn word_indices_group_by_transcript(
+    /**
+    Add a transcript to the index as `transcript_path`, replacing any transcript already there
+    - It keeps its own id unless another transcript has it (such as a copied file), otherwise it gets
+      the id of the transcript it replaces or an unsaved one (see [`UNSAVED_IDS_START`]), which is
+      only kept in memory until [`command_migrate`](crate::convert::command_migrate) saves an id to
+      the file
+    - Files indexed by an older tokenizer are indexed again (see [`HskFile::reindex_if_stale`])
+    - Call [`Searcher::update_term_statistics`] once done inserting
+    */
+    pub fn insert_transcript(
+        &mut self,
+        transcript_path: String,
+        mut file: HskFile,
+    ) -> TranscriptId {
+        let replaced_id = self.remove_transcript(&transcript_path);
+        let transcript_id = match file.id {
+            Some(id) if !self.transcript_paths.contains_key(&id) => id,
+            _ => replaced_id.unwrap_or_else(|| self.next_unsaved_id()),
+        };
+        self.transcript_ids
+            .insert(transcript_path.clone(), transcript_id);
+        self.transcript_paths.insert(transcript_id, transcript_path);
+        file.reindex_if_stale();
+        let citations = find_citations(file.words.iter().map(|word| word.word.as_str()));
+        if !citations.is_empty() {
+            self.citations.insert(transcript_id, Arc::new(citations));
+        }
+        let words = self.vocabulary.index_words(file.words);
+        self.transcript_words.insert(transcript_id, Arc::new(words));
+        if let Some(provenance) = file.provenance {
+            self.transcript_provenance.insert(transcript_id, provenance);
+        }
+        self.transcript_metadata
+            .insert(transcript_id, file.metadata);
+        for (word, indices) in file.word_index_map {
+            let word = self.vocabulary.intern(&word);
+            if !self.map.contains_key(&word) {
+                self.add_indexed_word(word);
+            }
+            let entry = Arc::make_mut(self.map.entry(word).or_default());
+            // keep entries ordered by transcript id
+            let position = entry.partition_point(|(id, _)| *id < transcript_id);
+            entry.insert(position, (transcript_id, Postings::from_sorted(&indices)));
+        }
+        transcript_id
+    }
+
+    /// An id for a transcript without one of its own, past every unsaved id that is in use
+    fn next_unsaved_id(&self) -> TranscriptId {
+        self.transcript_paths
+            .range(UNSAVED_IDS_START..)
+            .next_back()
+            .map_or(UNSAVED_IDS_START, |(last_id, _)| last_id + 1)
+    }
+
+    /// Remove a transcript from the index, returning the id it had
+    /// - Call [`Searcher::update_term_statistics`] once done removing
+    pub fn remove_transcript(&mut self, transcript_path: &str) -> Option<TranscriptId> {
+        let transcript_id = self.transcript_ids.remove(transcript_path)?;
+        self.transcript_paths.remove(&transcript_id);
+        self.transcript_provenance.remove(&transcript_id);
+        self.transcript_metadata.remove(&transcript_id);
+        self.citations.remove(&transcript_id);
+        let transcript_words = self
+            .transcript_words
+            .remove(&transcript_id)
+            .unwrap_or_default();
+        let words: BTreeSet<_> = index_tokens(
+            transcript_words
+                .iter()
+                .map(|word| self.vocabulary.get(word.token)),
+        )
+        .into_iter()
+        .filter_map(|(_, token)| self.vocabulary.id(&token))
+        .collect();
+        for word in words {
+            let Some(entry) = self.map.get_mut(&word) else {
+                continue;
+            };
+            let findings = entry.len();
+            Arc::make_mut(entry).retain(|(id, _)| *id != transcript_id);
+            // only what this transcript used is released, even if it was indexed differently
+            if entry.len() == findings {
+                continue;
+            }
+            if entry.is_empty() {
+                self.map.remove(&word);
+                self.remove_indexed_word(word);
+            }
+            self.vocabulary.release(word);
+        }
+        self.vocabulary.release_words(&transcript_words);
+        Some(transcript_id)
+    }
+
+    /// Add a word that is indexed for the first time to the word lists
+    fn add_indexed_word(&mut self, id: TokenId) {
+        let word = self.vocabulary.get(id);
+        self.stems
+            .entry(self.stemmer.stem(word))
+            .or_default()
+            .push(id);
+        if word.chars().count() >= MIN_PHONETIC_LENGTH {
+            self.phonetic_words
+                .entry(phonetic_key(word))
+                .or_default()
+                .push(id);
+        }
+        self.fuzzy_words.insert(word);
+    }
+
+    /// Remove a word that is no longer indexed from the word lists (before it leaves the
+    /// vocabulary)
+    fn remove_indexed_word(&mut self, id: TokenId) {
+        let word = self.vocabulary.get(id);
+        let remove_from = |words: &mut SharedMap<String, Vec<TokenId>>, key: String| {
+            let Some(ids) = words.get_mut(&key) else {
+                return;
+            };
+            ids.retain(|other| *other != id);
+            if ids.is_empty() {
+                words.remove(&key);
+            }
+        };
+        remove_from(&mut self.stems, self.stemmer.stem(word));
+        if word.chars().count() >= MIN_PHONETIC_LENGTH {
+            remove_from(&mut self.phonetic_words, phonetic_key(word));
+        }
+        self.fuzzy_words.remove(word);
+    }
+
+    /// Refresh what is derived from every transcript at once, once done inserting and removing
+    pub fn update_term_statistics(&mut self) {
+        self.term_statistics = TermStatistics::new(&self.transcript_words);
+    }
+
+    /// Where `word` (as it is indexed) is, in every transcript that has it
+    fn postings(&self, word: &str) -> Option<&Arc<Vec<TranscriptWordIndices>>> {
+        self.map.get(&self.vocabulary.id(word)?)
+    }
+
+    /// Every indexed word starting with `prefix` (every indexed word when it is empty), in order
+    pub fn indexed_words<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.vocabulary
+            .starting_with(prefix)
+            .filter(|(_, id)| self.map.contains_key(id))
+            .map(|(word, _)| word)
+    }
+
+    pub fn get_transcript_id(&self, transcript_path: &str) -> Option<TranscriptId> {
+        self.transcript_ids.get(transcript_path).copied()
+    }
+
+    /// The transcripts that pass `filter`, or [`None`] when it lets every transcript through
+    pub fn filtered_transcripts(
         &self,
-        words: &Vec<String>,
-    ) -> Map<TranscriptId, Vec<WordIndices>> {
-        let words: Vec<&String> = words.iter().filter(|w| w.len() > 0).collect();
-        let mut transcript_to_indices: Map<TranscriptId, Vec<WordIndices>> = Map::default();
-
-        for findings in words.into_iter().filter_map(|word| self.map.get(word)) {
-            for finding in findings {
-                if let Some(existing_entry) = transcript_to_indices.get_mut(&finding.0) {
-                    // existing_entry.extend(finding.1);
-                    existing_entry.push(finding.1.clone());
-                } else {
-                    // transcript_to_indices.insert(finding.0, finding.1.clone());
-                    transcript_to_indices.insert(finding.0, vec![finding.1.clone()]);
+        filter: &TranscriptFilter,
+    ) -> Option<BTreeSet<TranscriptId>> {
+        if filter.is_empty() {
+            return None;
+        }
+        let matcher = filter.matcher();
+        let no_metadata = TranscriptMetadata::default();
+        Some(
+            self.transcript_paths
+                .iter()
+                .filter(|(id, path)| {
+                    let metadata = self.transcript_metadata.get(id).unwrap_or(&no_metadata);
+                    matcher(**id, path, metadata)
+                })
+                .map(|(id, _)| *id)
+                .collect(),
+        )
+    }
+
+    /// The stop words used when searching `collection`
+    pub fn stop_words(&self, collection: Option<&str>) -> &Vec<String> {
+        collection
+            .and_then(|collection| self.collection_stop_words.get(collection))
+            .unwrap_or(&self.stop_words)
+    }
+
+    /**
+    Where a query term is, in every transcript (whether or not it passes the filter)
+    - A word or phrase matches every word and phrase in its group of [`Synonyms`] when
+      [`QueryParams::synonyms`] is set (and nothing else below applies to it)
+    - A single word also matches its [`QueryParams::similar_words`], every word with the same stem
+      (see [`Stemmer`]) when [`QueryParams::stem`] is set, and `fuzzy_words`
+    */
+    fn find_term(
+        &self,
+        term: &QueryTerm,
+        params: &QueryParams,
+        fuzzy_words: &[String],
+    ) -> TermFindings<'_> {
+        if let Some(group) = params
+            .synonyms
+            .then(|| self.synonyms.group(&term.words))
+            .flatten()
+        {
+            return TermFindings::Occurrences(self.find_any_phrase(group));
+        }
+        if term.is_phrase() {
+            return TermFindings::Occurrences(self.find_phrase(&term.words));
+        }
+        let word = &term.words[0];
+        if term.is_wildcard() {
+            return self.find_any_word(&self.expand_wildcard(word));
+        }
+        let mut words = vec![word.clone()];
+        if let Some((_, similar_words)) = params
+            .similar_words
+            .iter()
+            .find(|(similar_to, _)| normalize_word(similar_to) == *word)
+        {
+            words.extend(similar_words.iter().map(|similar| normalize_word(similar)));
+        }
+        if params.stem {
+            let stem = self.stemmer.stem(word);
+            let stemmed = self.stems.get(&stem).into_iter().flatten();
+            words.extend(stemmed.map(|id| self.vocabulary.get(*id).to_string()));
+        }
+        words.extend(fuzzy_words.iter().cloned());
+        words.sort();
+        words.dedup();
+        if words.len() == 1 {
+            TermFindings::Word(self.postings(word).map_or(&[], |f| f.as_slice()))
+        } else {
+            self.find_any_word(&words)
+        }
+    }
+
+    /// The indexed words (other than itself) spelled closely enough to a single word term to match it
+    /// with [`QueryParams::fuzzy`]
+    fn fuzzy_matches(&self, term: &QueryTerm, params: &QueryParams) -> Vec<String> {
+        if term.is_phrase() || term.is_wildcard() {
+            return vec![];
+        }
+        let word = &term.words[0];
+        let is_fuzzy = match params.fuzzy {
+            FuzzyMode::Off => false,
+            FuzzyMode::Unmatched => self.postings(word).is_none(),
+            FuzzyMode::All => true,
+        };
+        if !is_fuzzy {
+            return vec![];
+        }
+        let max_distance = params.fuzzy_distance.unwrap_or(CONFIG.fuzzy_distance);
+        self.fuzzy_words
+            .find(word, allowed_distance(word, max_distance))
+            .into_iter()
+            .filter(|(_, distance)| *distance > 0)
+            .map(|(similar, _)| similar.to_string())
+            .collect()
+    }
+
+    /// The indexed words (other than itself) that sound like a single word term
+    fn phonetic_matches(&self, term: &QueryTerm) -> Vec<String> {
+        if term.is_phrase() || term.is_wildcard() {
+            return vec![];
+        }
+        self.sounds_like(&term.words[0])
+    }
+
+    /// The indexed words (other than `word`) with the same [`phonetic_key`] as `word`
+    fn sounds_like(&self, word: &str) -> Vec<String> {
+        if word.chars().count() < MIN_PHONETIC_LENGTH {
+            return vec![];
+        }
+        let mut similar: Vec<String> = self
+            .phonetic_words
+            .get(&phonetic_key(word))
+            .into_iter()
+            .flatten()
+            .map(|id| self.vocabulary.get(*id))
+            .filter(|similar| *similar != word)
+            .map(String::from)
+            .collect();
+        similar.sort();
+        similar
+    }
+
+    /// Where any of `words` are, as if they were all the same word
+    fn find_any_word(&self, words: &[String]) -> TermFindings<'_> {
+        let phrases: Vec<Vec<String>> = words.iter().map(|word| vec![word.clone()]).collect();
+        TermFindings::Occurrences(self.find_any_phrase(&phrases))
+    }
+
+    /// Where any of `phrases` (which can also be single words) are, as if they were all the same word
+    fn find_any_phrase(&self, phrases: &[Vec<String>]) -> Map<TranscriptId, Vec<Occurrence>> {
+        let mut occurrences: Map<TranscriptId, Vec<Occurrence>> = Map::default();
+        for phrase in phrases {
+            if let [word] = phrase.as_slice() {
+                for (transcript_id, postings) in
+                    self.postings(word).into_iter().flat_map(|f| f.iter())
+                {
+                    let word_occurrences =
+                        postings.iter().map(|start| Occurrence { start, length: 1 });
+                    occurrences
+                        .entry(*transcript_id)
+                        .or_default()
+                        .extend(word_occurrences);
+                }
+                continue;
+            }
+            for (transcript_id, phrase_occurrences) in self.find_phrase(phrase) {
+                occurrences
+                    .entry(transcript_id)
+                    .or_default()
+                    .extend(phrase_occurrences);
+            }
+        }
+        sort_occurrences(&mut occurrences);
+        occurrences
+    }
+
+    /// Where `words` are found one after another, in every transcript
+    fn find_phrase(&self, words: &[String]) -> Map<TranscriptId, Vec<Occurrence>> {
+        let starts = self.phrase_starts(words, &None).unwrap_or_default();
+        starts
+            .into_iter()
+            .map(|(transcript_id, starts)| {
+                let occurrences = starts.into_iter().map(|start| Occurrence {
+                    start,
+                    length: words.len(),
+                });
+                (transcript_id, occurrences.collect())
+            })
+            .collect()
+    }
+
+    /// Where every reference to a passage that overlaps `passage` is, in every transcript
+    fn find_passage(&self, passage: &Passage) -> Map<TranscriptId, Vec<Occurrence>> {
+        self.citations
+            .iter()
+            .map(|(transcript_id, citations)| {
+                let occurrences = citations
+                    .iter()
+                    .filter(|citation| citation.passage.overlaps(passage))
+                    .map(|citation| Occurrence {
+                        start: citation.start,
+                        length: citation.length,
+                    });
+                (*transcript_id, occurrences.collect())
+            })
+            .collect()
+    }
+
+    /// Every indexed word that a wildcard such as `redeem*` or `re*ed` matches
+    pub fn expand_wildcard(&self, wildcard: &str) -> Vec<String> {
+        let Ok(pattern) = Pattern::new(wildcard) else {
+            return vec![];
+        };
+        let prefix = wildcard.split(WILDCARD).next().unwrap_or_default();
+        self.indexed_words(prefix)
+            .filter(|word| pattern.matches(word))
+            .map(String::from)
+            .collect()
+    }
+
+    /**
+    Where each term of a query is in every transcript that has any of them
+    - `findings` and `excluded` are from [`Searcher::find_term`], where `findings` is in the same
+      order as `terms`
+    - Transcripts without every required term are left out, as are transcripts with an excluded term
+      when `exclude_transcripts` is set
+    */
+    fn word_indices_group_by_transcript<'a>(
+        &self,
+        terms: &[QueryTerm],
+        findings: &'a [TermFindings],
+        excluded: &'a [TermFindings],
+        transcripts: &Option<BTreeSet<TranscriptId>>,
+        exclude_transcripts: bool,
+    ) -> Map<TranscriptId, TranscriptMatches<'a>> {
+        let is_included = |transcript_id: &TranscriptId| {
+            transcripts
+                .as_ref()
+                .is_none_or(|transcripts| transcripts.contains(transcript_id))
+        };
+        let mut transcript_to_indices: Map<TranscriptId, TranscriptMatches> = Map::default();
+
+        for (idx, term_findings) in findings.iter().enumerate() {
+            for (transcript_id, indices) in term_findings.iter().filter(|(id, _)| is_included(id)) {
+                let entry = transcript_to_indices
+                    .entry(transcript_id)
+                    .or_insert_with(|| TranscriptMatches::new(terms.len()));
+                entry.terms[idx] = Some(indices);
+            }
+        }
+        transcript_to_indices.retain(|_, matches| {
+            terms
+                .iter()
+                .zip(matches.terms.iter())
+                .all(|(term, indices)| !term.is_required() || indices.is_some())
+        });
+
+        for term_findings in excluded {
+            for (transcript_id, indices) in term_findings.iter() {
+                if let Some(matches) = transcript_to_indices.get_mut(&transcript_id) {
+                    matches.excluded.push(indices);
                 }
             }
         }
+        if exclude_transcripts {
+            transcript_to_indices.retain(|_, matches| matches.excluded.is_empty());
+        }
         transcript_to_indices
     }
 
-    pub fn search_exact(&self, query: impl AsRef<str>, page: usize) -> Option<Vec<QueryResult>> {
+    /**
+    Where `words` are found one after another (as the word index of the first one), in every
+    transcript that passes the filter
+    - [`None`] when one of the words isn't in any transcript
+    */
+    fn phrase_starts(
+        &self,
+        words: &[String],
+        transcripts: &Option<BTreeSet<TranscriptId>>,
+    ) -> Option<Map<TranscriptId, Vec<usize>>> {
+        // the transcripts that have every word so far, starting with those that have the first one
+        let first = self.postings(words.first()?)?;
+        let first = first.iter().filter(|(transcript_id, _)| {
+            transcripts
+                .as_ref()
+                .is_none_or(|transcripts| transcripts.contains(transcript_id))
+        });
+        let mut valid_transcripts = first
+            .clone()
+            .map(|(transcript_id, _)| *transcript_id)
+            .collect::<Vec<_>>();
+        // transcript to word starts
+        let mut valid_starts = first
+            .map(|(tid, word_indices)| (*tid, word_indices.to_vec()))
+            .collect::<BTreeMap<_, _>>();
+
+        for (idx, word) in words.iter().enumerate().skip(1) {
+            // remove next that aren't part of existing
+            let next = self.postings(word)?;
+            let next_transcripts: Vec<_> = next
+                .iter()
+                .filter(|(tid, _)| valid_transcripts.contains(tid))
+                .map(|(tid, _)| *tid)
+                .collect();
+            // remove existing valid that aren't continued
+            valid_transcripts.retain(|vt| next_transcripts.contains(vt));
+            // remove valid starts where transcript isn't continued
+            valid_starts.retain(|key, _| valid_transcripts.contains(key));
+            for it in next.iter() {
+                let Some(word_indices) = valid_starts.get_mut(&it.0) else {
+                    continue;
+                };
+                // both are sorted, so walk through them together
+                let mut next_indices = it.1.iter().peekable();
+                word_indices.retain(|wi| {
+                    while next_indices.next_if(|ni| *ni < *wi + idx).is_some() {}
+                    next_indices.peek() == Some(&(*wi + idx))
+                });
+            }
+        }
+        Some(valid_starts)
+    }
+
+    pub fn search_exact(
+        &self,
+        query: impl AsRef<str>,
+        page: usize,
+        filter: &TranscriptFilter,
+    ) -> Option<Vec<QueryResult>> {
+        let transcripts = self.filtered_transcripts(filter);
         let words: Vec<_> = query
             .as_ref()
             .split_whitespace()
             .map(|word| normalize_word(word))
             .filter(|word| word.len() > 0)
             .collect();
-        let mut iter = words.iter();
-
-        let mut idx = 1;
-        let transcript_starts = {
-            // let findings = words.map(|word| self.map.get(&word));
-            // what i need is a list of valid transcripts, which starts out as all
-            // transcripts that contain the first word
-            // but then shrinks as the next one doesn't contain it
-            let first = self.map.get(iter.next()?)?;
-            let mut valid_transcripts = first
-                .iter()
-                .map(|(transcript_id, _)| *transcript_id)
-                .collect::<Vec<_>>();
-            // transcript to word starts
-            let mut valid_starts = first
-                .into_iter()
-                .map(|(tid, word_indices)| (*tid, word_indices.clone()))
-                .collect::<BTreeMap<_, _>>();
-
-            while let Some(word) = iter.next() {
-                // remove next that aren't part of existing
-                let next = self.map.get(word)?;
-                let next_transcripts: Vec<_> = next
-                    .iter()
-                    .filter(|(tid, _)| valid_transcripts.contains(tid))
-                    .map(|(tid, _)| *tid)
-                    .collect();
-                // remove existing valid that aren't continued
-                valid_transcripts = valid_transcripts
-                    .into_iter()
-                    .filter(|vt| next_transcripts.contains(vt))
-                    .collect();
-
-                // remove valid starts where transcript isn't continued
-                valid_starts = valid_starts
-                    .into_iter()
-                    .filter(|(key, _)| valid_transcripts.contains(key))
-                    // .map(|(key, word_indices)| (key, word_indices.into_iter().filter(|wi| next)))
-                    .collect();
-                for it in next {
-                    let Some(word_indices) = valid_starts.get_mut(&it.0) else {
-                        continue;
-                    };
-                    *word_indices = word_indices
-                        .iter()
-                        .cloned()
-                        .filter(|wi| it.1.contains(*wi + idx))
-                        .collect();
-                }
-                idx += 1;
-            }
-            valid_starts
-        };
+        let transcript_starts = self.phrase_starts(&words, &transcripts)?;
+        let idx = words.len();
         // dbg!(&transcript_starts);
         let mut results = vec![];
         for (transcript, value) in transcript_starts.iter() {
@@ -173,7 +1140,7 @@ impl Searcher {
                     .as_ref()
                     .iter()
                     .map(|word| QueryWord {
-                        word: word.word.clone(),
+                        word: self.vocabulary.get(word.token).to_string(),
                         start: word.start,
                         end: word.end,
                         matched: true,
@@ -183,15 +1150,20 @@ impl Searcher {
                 let element_count = idx;
                 let transcript = self
                     .transcript_paths
-                    .get(*transcript)
+                    .get(transcript)
                     .expect("It exists")
                     .clone();
 
+                // the whole query, in order and with nothing in between
+                let ordering = 1.0;
+                let score = 0.0;
                 results.push(QueryResult::new(
                     transcript,
                     words,
                     unique_count,
                     element_count,
+                    ordering,
+                    score,
                 ));
             }
         }
@@ -204,84 +1176,191 @@ impl Searcher {
         // todo!()
     }
 
-    pub fn search(
-        &self,
-        query: impl AsRef<str>,
-        context: usize,
-        page: usize,
-        remove_stop_words: bool,
-    ) -> Vec<QueryResult> {
-        // perhaps split at more than white space, for example `:` in 1 John 3:10
-        let words = query
-            .as_ref()
-            .split_whitespace()
-            .map(|word| normalize_word(word))
-            .filter(|word| word.len() > 0);
+    /**
+    - `+word` terms have to be in every result, and `-word` terms can't be (see [`parse_query`])
+    - `"quoted phrases"` have to be found word for word, and are otherwise treated like one word
+    - `redeem*` (or `re*ed`) matches every word it expands to as one word
+    - Queries with `AND`, `OR`, `NOT`, `NEAR/n` or parentheses only give the segments where the
+      expression is true (see [`parse_boolean_query`]), and keep their stop words
+    - With [`QueryParams::reference`], every result has a citation of the passage (which is all
+      that is searched for when the query is empty)
+    - Anything not given in `params` uses the config's value (see [`QueryParams`])
+    */
+    pub fn search(&self, params: &QueryParams) -> SearchResults {
+        let filter = &params.filter;
+        let parameters = CONFIG.parameters(filter.collection.as_deref());
+        let context = params.context.unwrap_or(parameters.context_size.value);
+        let remove_stop_words = params
+            .remove_stop_words
+            .unwrap_or(parameters.remove_stop_words.value);
+        // compared with the words of the query, which has its numbers as digits
+        let stop_words: Vec<String> = self
+            .stop_words(filter.collection.as_deref())
+            .iter()
+            .map(|word| query_word(word))
+            .collect();
+        let keep_words: Vec<String> = params
+            .keep_words
+            .iter()
+            .map(|word| query_word(word))
+            .collect();
+        // `validate` rejects the boolean queries that don't parse
+        let boolean = is_boolean_query(&params.query)
+            .then(|| parse_boolean_query(&params.query).ok())
+            .flatten();
+        let (excluded, mut terms): (Vec<QueryTerm>, Vec<QueryTerm>) = match &boolean {
+            // every term has a place in the expression, so none of them are left out as stop words
+            Some(boolean) => (vec![], boolean.terms.clone()),
+            None => {
+                // perhaps split at more than white space, for example `:` in 1 John 3:10
+                let terms = parse_query(&params.query);
 
-        let words: Vec<String> = if remove_stop_words {
-            words
-                .filter(|word| !self.stop_words.contains(word))
-                .collect()
-        } else {
-            words.collect()
+                // required and excluded terms were asked for, so they are kept even if they are
+                // stop words
+                let terms = terms.into_iter().filter(|term| {
+                    !remove_stop_words
+                        || term.presence != Presence::Optional
+                        || term.is_phrase()
+                        || !stop_words.contains(&term.words[0])
+                        || keep_words.contains(&term.words[0])
+                });
+                terms.partition(|term| term.is_excluded())
+            }
+        };
+        let mut negated: Vec<bool> = match &boolean {
+            Some(boolean) => boolean.negated_terms(),
+            None => vec![false; terms.len()],
         };
 
-        // let words: Vec<String> = words.collect();
-        let transcript_indices = self.word_indices_group_by_transcript(&words);
-        let allowed_range = if remove_stop_words {
-            words.len() * CONFIG.word_distance_with_stop_words_removed
+        // words that sound like a query word are only as good as fuzzy matches, and terms after NOT
+        // don't get either (like excluded terms, below)
+        let mut fuzzy_words: Vec<Vec<String>> = terms
+            .iter()
+            .zip(negated.iter())
+            .map(|(term, negated)| {
+                if *negated {
+                    return vec![];
+                }
+                let mut words = self.fuzzy_matches(term, params);
+                if params.phonetic {
+                    words.extend(self.phonetic_matches(term));
+                }
+                words.sort();
+                words.dedup();
+                words
+            })
+            .collect();
+        let mut findings: Vec<TermFindings> = terms
+            .iter()
+            .zip(fuzzy_words.iter())
+            .map(|(term, fuzzy_words)| self.find_term(term, params, fuzzy_words))
+            .collect();
+        // the citations of the passage are one more term, which every result has
+        if let Some(passage) = params
+            .reference
+            .as_deref()
+            .and_then(|reference| Passage::parse(reference).ok())
+        {
+            terms.push(QueryTerm {
+                words: vec![passage.to_string()],
+                presence: Presence::Required,
+            });
+            fuzzy_words.push(vec![]);
+            negated.push(false);
+            findings.push(TermFindings::Occurrences(self.find_passage(&passage)));
+        }
+        let required = terms
+            .iter()
+            .enumerate()
+            .filter(|(_, term)| term.is_required())
+            .map(|(idx, _)| WordId::from_index(idx))
+            .collect::<WordIdSet>();
+        // excluding words that are only spelled like an excluded word would be surprising
+        let excluded: Vec<TermFindings> = excluded
+            .iter()
+            .map(|term| self.find_term(term, params, &[]))
+            .collect();
+        let transcripts = self.filtered_transcripts(filter);
+        let transcript_indices = self.word_indices_group_by_transcript(
+            &terms,
+            &findings,
+            &excluded,
+            &transcripts,
+            params.exclude_transcripts,
+        );
+        let term_lengths: Vec<usize> = findings
+            .iter()
+            .map(|findings| findings.max_length())
+            .collect();
+        let word_distance = params.word_distance.unwrap_or(if remove_stop_words {
+            parameters.word_distance_with_stop_words_removed.value
         } else {
-            words.len() * CONFIG.word_distance
+            parameters.word_distance.value
+        });
+        // the words of a phrase are right next to each other
+        let phrase_words: usize = term_lengths.iter().map(|length| length - 1).sum();
+        let merged_terms = negated.iter().filter(|negated| !**negated).count();
+        let near_distance = boolean
+            .as_ref()
+            .map_or(0, |boolean| boolean.expression.max_near_distance());
+        let allowed_range = (merged_terms * word_distance).max(near_distance) + phrase_words;
+
+        let relevance = Relevance::new(
+            findings
+                .iter()
+                .map(|findings| findings.document_frequency()),
+            fuzzy_words,
+            &self.term_statistics,
+            &self.transcript_words,
+            &self.vocabulary,
+        );
+        let search = SegmentSearch {
+            allowed_range,
+            window_seconds: params.window_seconds,
+            transcript_words: &self.transcript_words,
+            required,
+            negated: negated
+                .iter()
+                .enumerate()
+                .filter(|(_, negated)| **negated)
+                .map(|(idx, _)| WordId::from_index(idx))
+                .collect(),
+            expression: boolean.as_ref().map(|boolean| &boolean.expression),
+            has_phrases: term_lengths.iter().any(|length| *length > 1),
+            relevance,
         };
 
-        let mut results: Map<usize, Map<usize, Vec<(TranscriptId, WordSegmentRange)>>> =
-            Map::default();
-
-        for (transcript_id, list_of_word_indices) in transcript_indices {
-            let word_segment_ranges = merge_special(list_of_word_indices, allowed_range);
-            for sr in word_segment_ranges {
-                let unique_count = sr.set.unique_count();
-                let element_count = sr.elements.len();
-                let qr = (transcript_id, sr);
-                if let Some(unique_group) = results.get_mut(&unique_count) {
-                    if let Some(element_group) = unique_group.get_mut(&element_count) {
-                        element_group.push(qr);
-                    } else {
-                        unique_group.insert(element_count, vec![qr]);
-                    }
-                } else {
-                    let mut element_group = Map::default();
-                    element_group.insert(element_count, vec![qr]);
-                    _ = results.insert(unique_count, element_group);
-                }
+        let mut results = group_segments_in_parallel(transcript_indices, &search);
+        // this is stable, so segments that tie stay in transcript id order
+        results.sort_by(|(_, a), (_, b)| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| compare_ranges(a, b))
+        });
+
+        let mut facets = Facets::default();
+        for (transcript_id, _) in results.iter() {
+            if let Some(metadata) = self.transcript_metadata.get(transcript_id) {
+                facets.add(metadata);
             }
         }
 
-        let page_size = CONFIG.page_size();
-        let skip_count = page * page_size;
+        let page_size = params.page_size.unwrap_or(parameters.page_size.value);
+        let skip_count = params.page * page_size;
         let take_count = skip_count + page_size;
         let mut page_results = vec![];
         for (transcript_id, sr) in results
-            .into_values()
-            // Higher key means higher count of unique elements
-            .rev()
-            // Higher key means higher count of total elements
-            .flat_map(|m| m.into_values().rev())
-            .flatten()
+            .into_iter()
             // Take only for current page
             .skip(skip_count)
             .take(take_count)
         {
-            let start = if context > sr.min {
-                0
-            } else {
-                sr.min - context
-            };
             let transcript_words = self
                 .transcript_words
                 .get(&transcript_id)
                 .expect("It exists");
-            let end = std::cmp::min(sr.max + context, transcript_words.len() - 1);
+            let (start, end) =
+                context_bounds(transcript_words, &sr, context, params.context_seconds);
             // prev
             // let words = transcript_words[start..=end].to_vec();
             // let transcript = self.transcript_paths.get(transcript_id).expect("It exists");
@@ -296,7 +1375,7 @@ impl Searcher {
                     // let matched = sr.min <= this_word_id && this_word_id <= sr.max;
                     let matched = sr.elements.binary_search(&this_word_id).is_ok();
                     QueryWord {
-                        word: word.word.clone(),
+                        word: self.vocabulary.get(word.token).to_string(),
                         start: word.start,
                         end: word.end,
                         matched,
@@ -305,19 +1384,32 @@ impl Searcher {
                 .collect();
             let unique_count = sr.set.unique_count();
             let element_count = sr.elements.len();
-            let transcript = self.transcript_paths.get(transcript_id).expect("It exists");
+            let transcript = self
+                .transcript_paths
+                .get(&transcript_id)
+                .expect("It exists");
             page_results.push(QueryResult::new(
                 transcript.clone(),
                 words,
                 unique_count,
                 element_count,
+                sr.ordering,
+                sr.score,
             ));
         }
 
-        page_results
+        SearchResults {
+            results: page_results,
+            facets,
+        }
     }
 
-    pub fn diagnose_query<'a>(&'a self, query: impl AsRef<str>) -> QueryDiagnostics {
+    pub fn diagnose_query<'a>(
+        &'a self,
+        query: impl AsRef<str>,
+        collection: Option<&str>,
+    ) -> QueryDiagnostics {
+        let stop_words = self.stop_words(collection);
         let words: Vec<String> = query
             .as_ref()
             .split_whitespace()
@@ -328,21 +1420,37 @@ impl Searcher {
         let (ignored_words, kept_words): (Vec<String>, Vec<String>) = words
             .clone()
             .into_iter()
-            .partition(|word| self.stop_words.contains(word));
+            .partition(|word| stop_words.contains(word));
 
         let similar_words: BTreeMap<String, Vec<String>> = words
             .iter()
             .map(|word| {
                 (
                     word.clone(),
-                    find_all_extended_words(&self.all_words, word).unwrap_or(vec![]),
+                    self.indexed_words(word).map(String::from).collect(),
                 )
             })
             .collect();
 
         let (kept_words, unmatched_words): (Vec<String>, Vec<String>) = kept_words
             .into_iter()
-            .partition(|word| self.all_words.binary_search(word).is_ok());
+            .partition(|word| self.postings(word).is_some());
+
+        let fuzzy_words: BTreeMap<String, Vec<String>> = unmatched_words
+            .iter()
+            .map(|word| {
+                let max_distance = allowed_distance(word, CONFIG.fuzzy_distance);
+                let similar = self.fuzzy_words.find(word, max_distance);
+                let similar = similar.into_iter().map(|(word, _)| word.to_string());
+                (word.clone(), similar.collect())
+            })
+            .collect();
+
+        let phonetic_words: BTreeMap<String, Vec<String>> = words
+            .iter()
+            .map(|word| (word.clone(), self.sounds_like(word)))
+            .filter(|(_, similar)| !similar.is_empty())
+            .collect();
 
         QueryDiagnostics {
             words,
@@ -350,28 +1458,276 @@ impl Searcher {
             kept_words,
             unmatched_words,
             similar_words,
+            fuzzy_words,
+            phonetic_words,
         }
     }
-    pub fn get_transcript_words(&self, transcript_path: String) -> Option<Vec<Word>> {
-        let transcript_id = self
-            .transcript_paths
+    /// Every transcript's provenance, keyed by transcript id like `/ids`
+    pub fn provenance_listing(&self, stale_only: bool) -> Map<TranscriptId, TranscriptProvenance> {
+        self.transcript_paths
             .iter()
-            .position(|path| *path == transcript_path)?;
+            .map(|(id, path)| {
+                let provenance = self.transcript_provenance.get(id).cloned();
+                let stale_reasons = match &provenance {
+                    Some(provenance) => provenance.stale_reasons(),
+                    None => vec![String::from("no provenance was recorded")],
+                };
+                (
+                    *id,
+                    TranscriptProvenance {
+                        path: path.clone(),
+                        provenance,
+                        stale_reasons,
+                    },
+                )
+            })
+            .filter(|(_, listing)| !stale_only || !listing.stale_reasons.is_empty())
+            .collect()
+    }
+
+    pub fn get_transcript_words(&self, transcript_path: String) -> Option<Vec<Word>> {
+        let transcript_id = self.get_transcript_id(&transcript_path)?;
         let words = self.transcript_words.get(&transcript_id)?;
-        Some(words.clone())
+        Some(
+            words
+                .iter()
+                .map(|word| self.vocabulary.word(word))
+                .collect(),
+        )
+    }
+}
+
+/// Which transcripts a search looks through (every transcript when nothing is given)
+#[derive(Clone, Debug, Default, FromForm, Deserialize)]
+#[serde(default)]
+pub struct TranscriptFilter {
+    /// Only transcripts in this collection (see [`AppConfig::collections`](crate::app_config::AppConfig::collections))
+    pub collection: Option<String>,
+    /// Only transcripts whose path starts with this, such as `2023/romans/`
+    pub prefix: Option<String>,
+    /// Only transcripts whose path matches this glob, such as `*/romans/**`
+    /// - `*` doesn't match across `/`, but `**` does
+    pub glob: Option<String>,
+    /// Only these transcript ids (or all of them when empty)
+    pub include: Vec<TranscriptId>,
+    /// Never these transcript ids
+    pub exclude: Vec<TranscriptId>,
+    /// Only transcripts dated on or after this (`YYYY-MM-DD`, `YYYY-MM` or `YYYY`, which include the
+    /// whole month or year)
+    pub from: Option<String>,
+    /// Only transcripts dated on or before this (`YYYY-MM-DD`, `YYYY-MM` or `YYYY`, which include the
+    /// whole month or year)
+    pub to: Option<String>,
+    /// Only transcripts by this speaker (ignoring case)
+    pub speaker: Option<String>,
+    /// Only transcripts in this series (ignoring case)
+    pub series: Option<String>,
+    /// Only transcripts with every one of these tags (ignoring case)
+    pub tags: Vec<String>,
+}
+
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl TranscriptFilter {
+    pub fn collection(collection: Option<String>) -> Self {
+        Self {
+            collection,
+            ..Default::default()
+        }
+    }
+
+    /// Whether this lets every transcript through
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_none()
+            && self.prefix.is_none()
+            && self.glob.is_none()
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.from.is_none()
+            && self.to.is_none()
+            && self.speaker.is_none()
+            && self.series.is_none()
+            && self.tags.is_empty()
+    }
+
+    /// Rejects collections that aren't configured and globs that can't be parsed
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(collection) = &self.collection {
+            if !CONFIG.collections.contains_key(collection) {
+                return Err(format!("Unknown collection {collection:?}"));
+            }
+        }
+        if let Some(glob) = &self.glob {
+            Pattern::new(glob).map_err(|err| format!("Invalid glob {glob:?}: {err}"))?;
+        }
+        for date in [&self.from, &self.to].into_iter().flatten() {
+            if !is_valid_date(date) {
+                return Err(format!(
+                    "Invalid date {date:?}, expected `YYYY-MM-DD`, `YYYY-MM` or `YYYY`"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a transcript (by id, path and metadata) passes this filter
+    /// - Collections that don't exist and invalid globs match nothing
+    /// - Transcripts without a date never pass a date range
+    pub fn matcher(&self) -> impl Fn(TranscriptId, &str, &TranscriptMetadata) -> bool + '_ {
+        let collection_prefix = self
+            .collection
+            .as_ref()
+            .map(|collection| CONFIG.collection_prefix(collection));
+        let glob = self.glob.as_ref().map(|glob| Pattern::new(glob).ok());
+        let is_same = |expected: &Option<String>, actual: &Option<String>| {
+            expected.as_ref().is_none_or(|expected| {
+                actual
+                    .as_ref()
+                    .is_some_and(|actual| actual.eq_ignore_ascii_case(expected))
+            })
+        };
+        move |id, path, metadata| {
+            let in_collection = match &collection_prefix {
+                Some(prefix) => prefix
+                    .as_ref()
+                    .is_some_and(|prefix| is_in_prefix(path, prefix)),
+                None => true,
+            };
+            let has_prefix = self
+                .prefix
+                .as_ref()
+                .is_none_or(|prefix| path.starts_with(prefix.as_str()));
+            let matches_glob = match &glob {
+                Some(glob) => glob
+                    .as_ref()
+                    .is_some_and(|glob| glob.matches_with(path, GLOB_OPTIONS)),
+                None => true,
+            };
+            let is_included = self.include.is_empty() || self.include.contains(&id);
+            let is_excluded = self.exclude.contains(&id);
+            let in_date_range = match (&self.from, &self.to) {
+                (None, None) => true,
+                (from, to) => metadata.date.as_ref().is_some_and(|date| {
+                    from.as_ref()
+                        .is_none_or(|from| compare_dates(date, from).is_ge())
+                        && to.as_ref().is_none_or(|to| compare_dates(date, to).is_le())
+                }),
+            };
+            let has_tags = self.tags.iter().all(|tag| {
+                metadata
+                    .tags
+                    .iter()
+                    .any(|transcript_tag| transcript_tag.eq_ignore_ascii_case(tag))
+            });
+            in_collection
+                && has_prefix
+                && matches_glob
+                && is_included
+                && !is_excluded
+                && in_date_range
+                && is_same(&self.speaker, &metadata.speaker)
+                && is_same(&self.series, &metadata.series)
+                && has_tags
+        }
     }
 }
 
+/**
+Everything that a search can be given, such as the JSON body of `POST /search`
+- Anything left out (or [`None`]) uses the config's value for the searched collection (see
+  [`AppConfig::parameters`](crate::app_config::AppConfig::parameters)), and is rejected by
+  [`QueryParams::validate`] when the config doesn't allow it to be overwritten
+*/
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
 pub struct QueryParams {
+    /// See [`parse_query`] for its syntax, or [`parse_boolean_query`] when it has operators (see
+    /// [`is_boolean_query`])
+    pub query: String,
     pub page: usize,
-    pub context: usize,
-    pub remove_stop_words: bool,
-    pub word_distance: usize,
+    pub page_size: Option<usize>,
+    /// How many words to give on each side of a result
+    pub context: Option<usize>,
+    pub remove_stop_words: Option<bool>,
+    /// How far apart the words of a result can be, for each word of the query
+    pub word_distance: Option<usize>,
+    /// How many seconds apart the first and last words of a result can be, instead of
+    /// `word_distance` (which is still used for words without timestamps)
+    pub window_seconds: Option<f64>,
+    /// Give the words spoken within this many seconds on each side of a result, instead of `context`
+    /// (which is still used for words without timestamps)
+    pub context_seconds: Option<f64>,
+    /// Leave out whole transcripts with an excluded term, not just the results that have it
+    pub exclude_transcripts: bool,
+    /// Match every word with the same stem as a query word, such as "justified" for "justify"
+    pub stem: bool,
+    /// Match every word and phrase in the same group of [`Synonyms`] as a query word or phrase
+    pub synonyms: bool,
+    /// Also match words that sound like the query words (see [`phonetic_key`]), which rank below
+    /// exact matches
+    pub phonetic: bool,
+    /// Also match words spelled like the query words, which rank below exact matches
+    pub fuzzy: FuzzyMode,
+    /// How many letters can differ for a fuzzy match (see [`allowed_distance`])
+    pub fuzzy_distance: Option<usize>,
+    /// Stop words that are kept in this query
     pub keep_words: Vec<String>,
-    /// This means that I should merge these array indices together and treat them as the same word
-    /// in the search
+    /// Query words that also match the words given for them, as if they were the same word
     /// - This is basically a selected subset (by the user) of [`QueryDiagnostics::similar_words`]
     pub similar_words: BTreeMap<String, Vec<String>>,
+    /// Only give results with a citation of this scripture passage, such as `Rom 8:28` (see
+    /// [`Passage::parse`])
+    pub reference: Option<String>,
+    pub filter: TranscriptFilter,
+}
+
+impl QueryParams {
+    /// Rejects invalid filters, boolean queries and references that don't parse and the settings that
+    /// the config doesn't allow requests to overwrite
+    pub fn validate(&self) -> Result<(), String> {
+        self.filter.validate()?;
+        if is_boolean_query(&self.query) {
+            parse_boolean_query(&self.query)?;
+        }
+        if let Some(reference) = &self.reference {
+            Passage::parse(reference)?;
+        }
+        let parameters = CONFIG.parameters(self.filter.collection.as_deref());
+        parameters
+            .page_size
+            .validate_overwrite("page_size", &self.page_size)?;
+        parameters
+            .context_size
+            .validate_overwrite("context", &self.context)?;
+        parameters
+            .remove_stop_words
+            .validate_overwrite("remove_stop_words", &self.remove_stop_words)?;
+        parameters
+            .word_distance
+            .validate_overwrite("word_distance", &self.word_distance)?;
+        let seconds = [
+            ("window_seconds", self.window_seconds),
+            ("context_seconds", self.context_seconds),
+        ];
+        for (name, seconds) in seconds {
+            if seconds.is_some_and(|seconds| !seconds.is_finite() || seconds < 0.0) {
+                return Err(format!("{name} has to be a number of at least 0"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TranscriptProvenance {
+    pub path: String,
+    pub provenance: Option<Provenance>,
+    /// Empty when the transcript is up to date
+    pub stale_reasons: Vec<String>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -381,6 +1737,46 @@ pub struct QueryDiagnostics {
     pub kept_words: Vec<String>,
     pub unmatched_words: Vec<String>,
     pub similar_words: BTreeMap<String, Vec<String>>,
+    /// Indexed words spelled like each unmatched word, closest first (see [`FuzzyMode`])
+    pub fuzzy_words: BTreeMap<String, Vec<String>>,
+    /// Indexed words that sound like each query word, which `phonetic` searches also match
+    pub phonetic_words: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SearchResults {
+    /// The results on the requested page
+    pub results: Vec<QueryResult>,
+    /// Counts for all results (not just this page)
+    pub facets: Facets,
+}
+
+/// How many results come from transcripts with each value of the [`TranscriptMetadata`] fields
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Facets {
+    pub year: Map<String, usize>,
+    pub speaker: Map<String, usize>,
+    pub series: Map<String, usize>,
+    pub tags: Map<String, usize>,
+}
+
+impl Facets {
+    /// Count one result from a transcript with this metadata
+    pub fn add(&mut self, metadata: &TranscriptMetadata) {
+        let values = [
+            (&mut self.year, metadata.year()),
+            (&mut self.speaker, metadata.speaker.as_deref()),
+            (&mut self.series, metadata.series.as_deref()),
+        ];
+        for (counts, value) in values {
+            if let Some(value) = value {
+                *counts.entry(value.to_string()).or_default() += 1;
+            }
+        }
+        for tag in metadata.tags.iter() {
+            *self.tags.entry(tag.clone()).or_default() += 1;
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -398,6 +1794,13 @@ pub struct QueryResult {
     pub words: Vec<QueryWord>,
     pub unique_count: usize,
     pub element_count: usize,
+    /// How closely the matched words follow the order of the query, from 0 to 1 (see
+    /// [`WordSegmentRange::ordering`])
+    pub ordering: f64,
+    /// How relevant this is to the query, which results are sorted by (see
+    /// [`Relevance::score`])
+    /// - This is 0 for exact searches, where every result matches equally
+    pub score: f64,
 }
 
 impl QueryResult {
@@ -406,12 +1809,16 @@ impl QueryResult {
         words: Vec<QueryWord>,
         unique_count: usize,
         element_count: usize,
+        ordering: f64,
+        score: f64,
     ) -> Self {
         Self {
             transcript,
             words,
             unique_count,
             element_count,
+            ordering,
+            score,
         }
     }
 }
@@ -447,3 +1854,159 @@ impl SearchResult {
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Whether `query` is true for the segment of the word at `index`, in a transcript where each
+    /// term of the query is found at `positions` (in the order that the terms appear in)
+    fn matches_at(query: &str, positions: &[&[usize]], index: usize, allowed_range: usize) -> bool {
+        let query = parse_boolean_query(query).expect("It parses");
+        let postings: Vec<Postings> = positions
+            .iter()
+            .map(|indices| Postings::from_sorted(indices))
+            .collect();
+        let mut matches = TranscriptMatches::new(postings.len());
+        for (term, postings) in matches.terms.iter_mut().zip(postings.iter()) {
+            *term = (!postings.is_empty()).then_some(TermIndices::Word(postings));
+        }
+        let range = WordSegmentRange::new(index, WordId::from_index(0));
+        let window = surrounding_window(&range, &allowed_range, 100);
+        matches
+            .expression_starts(&query.expression, (range.min, range.max), window)
+            .is_some()
+    }
+
+    #[test]
+    fn not_looks_around_the_segment() {
+        // "faith works", where "works" is right after the segment of "faith"
+        assert!(!matches_at("faith NOT works", &[&[10], &[11]], 10, 5));
+        assert!(!matches_at("faith NOT works", &[&[10], &[5]], 10, 5));
+        assert!(matches_at("faith NOT works", &[&[10], &[16]], 10, 5));
+        assert!(matches_at("faith NOT works", &[&[10], &[]], 10, 5));
+    }
+
+    #[test]
+    fn or_needs_either_side() {
+        assert!(matches_at("grace OR mercy", &[&[], &[3]], 3, 5));
+        assert!(matches_at("grace OR mercy", &[&[3], &[]], 3, 5));
+        assert!(!matches_at(
+            "(grace OR mercy) faith",
+            &[&[3], &[], &[]],
+            3,
+            5
+        ));
+    }
+
+    #[test]
+    fn near_is_within_its_distance() {
+        let range = |min: usize, max: usize| {
+            let mut range = WordSegmentRange::new(min, WordId::from_index(0));
+            range.max = max;
+            range
+        };
+        let query = parse_boolean_query("covenant NEAR/2 blood").expect("It parses");
+        let covenant = Postings::from_sorted(&[10]);
+        for (blood, is_near) in [(12, true), (8, true), (13, false)] {
+            let blood = Postings::from_sorted(&[blood]);
+            let matches = TranscriptMatches {
+                terms: vec![
+                    Some(TermIndices::Word(&covenant)),
+                    Some(TermIndices::Word(&blood)),
+                ],
+                excluded: vec![],
+            };
+            let bounds = (8, 13);
+            let window = surrounding_window(&range(8, 13), &5, 100);
+            assert_eq!(
+                matches
+                    .expression_starts(&query.expression, bounds, window)
+                    .is_some(),
+                is_near
+            );
+        }
+    }
+
+    /// A searcher with a transcript of each text, named after its index, where each word takes a
+    /// second
+    pub(crate) fn searcher_with(
+        texts: &[&str],
+        stop_words: &[&str],
+        synonyms: Synonyms,
+    ) -> Searcher {
+        let stop_words = stop_words.iter().map(|word| word.to_string()).collect();
+        let mut searcher = Searcher::new(Stemmer::default(), stop_words, synonyms, Map::new());
+        for (id, text) in texts.iter().enumerate() {
+            let words = text
+                .split_whitespace()
+                .enumerate()
+                .map(|(idx, word)| Word {
+                    word: word.to_string(),
+                    start: Some(idx as f64),
+                    end: Some(idx as f64 + 1.0),
+                })
+                .collect();
+            let mut file = HskFile::from_words(words);
+            file.id = Some(id);
+            searcher.insert_transcript(id.to_string(), file);
+        }
+        searcher.update_term_statistics();
+        searcher
+    }
+
+    /// The transcripts that `params` finds results in
+    pub(crate) fn found_in(searcher: &Searcher, params: QueryParams) -> BTreeSet<String> {
+        searcher
+            .search(&params)
+            .results
+            .into_iter()
+            .map(|result| result.transcript)
+            .collect()
+    }
+
+    #[test]
+    fn numbers_are_removed_as_stop_words() {
+        let searcher = searcher_with(&["one god", "one one one"], &["one"], Synonyms::default());
+        let params = |query: &str, keep_words: &[&str]| QueryParams {
+            query: query.to_string(),
+            remove_stop_words: Some(true),
+            keep_words: keep_words.iter().map(|word| word.to_string()).collect(),
+            ..Default::default()
+        };
+        // "one" is indexed (and searched for) as "1"
+        assert_eq!(
+            found_in(&searcher, params("one god", &[])),
+            ["0".to_string()].into()
+        );
+        assert_eq!(
+            found_in(&searcher, params("one god", &["one"])),
+            ["0".to_string(), "1".to_string()].into()
+        );
+    }
+
+    #[test]
+    fn long_queries_match_every_word() {
+        let words: Vec<String> = (0..40).map(|idx| format!("word{idx}")).collect();
+        let text = words.join(" ");
+        let searcher = searcher_with(&[&text, &words[..20].join(" ")], &[], Synonyms::default());
+        let results = searcher
+            .search(&QueryParams {
+                query: text.clone(),
+                remove_stop_words: Some(false),
+                ..Default::default()
+            })
+            .results;
+        assert_eq!(results[0].transcript, "0");
+        assert_eq!(results[0].unique_count, 40);
+        assert_eq!(results[1].unique_count, 20);
+    }
+
+    #[test]
+    fn surrounding_window_stays_in_the_transcript() {
+        let mut range = WordSegmentRange::new(3, WordId::from_index(0));
+        range.max = 6;
+        assert_eq!(surrounding_window(&range, &5, 100), (0, 11));
+        assert_eq!(surrounding_window(&range, &5, 8), (0, 7));
+    }
+}
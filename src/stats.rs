@@ -0,0 +1,288 @@
+use std::mem::size_of;
+
+use serde::Serialize;
+
+use crate::hsk_file::{HskResult, Provenance, TranscriptMetadata};
+use crate::postings::Postings;
+use crate::scripture::Citation;
+use crate::searcher::{Map, Searcher, TranscriptFilter, TranscriptId};
+use crate::utils::Timer;
+use crate::vocabulary::{IndexedWord, TokenId};
+
+/// How many of the most common words are listed when not specified
+pub const DEFAULT_TOP_WORDS: usize = 20;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LibraryStats {
+    pub transcript_count: usize,
+    pub total_words: usize,
+    /// Summed [`TranscriptStats::duration`] of every transcript
+    pub total_hours: f64,
+    /// How many distinct (normalized) words there are
+    pub vocabulary_size: usize,
+    /// The most common words (excluding stop words) and how often each occurs
+    pub top_words: Vec<(String, usize)>,
+    /// Words with neither a start nor an end time
+    pub untimed_words: usize,
+    pub transcripts: Map<TranscriptId, TranscriptStats>,
+    /// This is the whole index, regardless of any filter
+    pub memory: MemoryStats,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TranscriptStats {
+    pub path: String,
+    pub words: usize,
+    /// Seconds from the first timestamp to the last (if it has any)
+    pub duration: Option<f64>,
+    pub untimed_words: usize,
+}
+
+/// Approximate bytes used by the parts of the [`Searcher`]
+#[derive(Clone, Debug, Serialize)]
+pub struct MemoryStats {
+    pub transcript_words: usize,
+    pub postings: usize,
+    pub vocabulary: usize,
+    /// Paths, provenance, metadata, scripture citations, stop words, stems and the word lists used
+    /// for suggestions, fuzzy matching and phonetic matching
+    pub other: usize,
+    pub total: usize,
+}
+
+impl Searcher {
+    /// Statistics about the transcripts that pass `filter`
+    /// - Its collection (if any) decides which stop words are left out of the top words
+    pub fn stats(&self, filter: &TranscriptFilter, top_words: usize) -> LibraryStats {
+        let transcripts = self.filtered_transcripts(filter);
+        let is_included = |id: &TranscriptId| transcripts.as_ref().is_none_or(|t| t.contains(id));
+
+        let transcript_stats: Map<TranscriptId, TranscriptStats> = self
+            .transcript_words
+            .iter()
+            .filter(|(id, _)| is_included(id))
+            .map(|(id, words)| {
+                let path = self.transcript_paths.get(id).cloned().unwrap_or_default();
+                (*id, TranscriptStats::new(path, words))
+            })
+            .collect();
+
+        let stop_words = self.stop_words(filter.collection.as_deref());
+        let mut word_counts: Vec<(String, usize)> = self
+            .map
+            .iter()
+            .map(|(word, findings)| (self.vocabulary.get(*word).to_string(), findings))
+            .filter(|(word, _)| !stop_words.contains(word))
+            .map(|(word, findings)| {
+                let count = findings
+                    .iter()
+                    .filter(|(id, _)| is_included(id))
+                    .map(|(_, postings)| postings.len())
+                    .sum();
+                (word, count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        // most common first, ties in alphabetical order
+        word_counts.sort_by(|(a_word, a_count), (b_word, b_count)| {
+            b_count.cmp(a_count).then_with(|| a_word.cmp(b_word))
+        });
+        word_counts.truncate(top_words);
+
+        let vocabulary_size = self
+            .map
+            .values()
+            .filter(|findings| findings.iter().any(|(id, _)| is_included(id)))
+            .count();
+
+        LibraryStats {
+            transcript_count: transcript_stats.len(),
+            total_words: transcript_stats.values().map(|t| t.words).sum(),
+            total_hours: transcript_stats
+                .values()
+                .filter_map(|t| t.duration)
+                .sum::<f64>()
+                / 3600.0,
+            vocabulary_size,
+            top_words: word_counts,
+            untimed_words: transcript_stats.values().map(|t| t.untimed_words).sum(),
+            transcripts: transcript_stats,
+            memory: self.memory_stats(),
+        }
+    }
+
+    fn memory_stats(&self) -> MemoryStats {
+        let strings_size = |strings: &Vec<String>| {
+            strings.capacity() * size_of::<String>()
+                + strings.iter().map(|s| s.capacity()).sum::<usize>()
+        };
+        let transcript_words = self
+            .transcript_words
+            .values()
+            .map(|words| words.capacity() * size_of::<IndexedWord>())
+            .sum();
+        let postings = self
+            .map
+            .iter()
+            .map(|(_, findings)| {
+                size_of::<TokenId>()
+                    + findings.capacity() * size_of::<(TranscriptId, Postings)>()
+                    + findings
+                        .iter()
+                        .map(|(_, postings)| postings.heap_size())
+                        .sum::<usize>()
+            })
+            .sum();
+        let vocabulary = self.vocabulary.heap_size();
+        // once in `transcript_paths` and once in `transcript_ids`
+        let paths: usize = 2 * self
+            .transcript_paths
+            .values()
+            .map(|p| p.capacity())
+            .sum::<usize>();
+        let other = paths
+            + self.transcript_provenance.len() * size_of::<Provenance>()
+            + self.transcript_metadata.len() * size_of::<TranscriptMetadata>()
+            + self
+                .citations
+                .values()
+                .map(|citations| citations.capacity() * size_of::<Citation>())
+                .sum::<usize>()
+            + self.fuzzy_words.heap_size()
+            + self
+                .phonetic_words
+                .iter()
+                .map(|(key, words)| key.capacity() + words.capacity() * size_of::<TokenId>())
+                .sum::<usize>()
+            + self
+                .stems
+                .iter()
+                .map(|(stem, words)| stem.capacity() + words.capacity() * size_of::<TokenId>())
+                .sum::<usize>()
+            + strings_size(&self.stop_words)
+            + self
+                .collection_stop_words
+                .values()
+                .map(strings_size)
+                .sum::<usize>();
+        MemoryStats {
+            transcript_words,
+            postings,
+            vocabulary,
+            other,
+            total: transcript_words + postings + vocabulary + other,
+        }
+    }
+}
+
+impl TranscriptStats {
+    fn new(path: String, words: &[IndexedWord]) -> Self {
+        let times = words
+            .iter()
+            .flat_map(|word| [word.start, word.end])
+            .flatten();
+        let first = times.clone().reduce(f64::min);
+        let last = times.reduce(f64::max);
+        Self {
+            path,
+            words: words.len(),
+            duration: first.zip(last).map(|(first, last)| last - first),
+            untimed_words: words
+                .iter()
+                .filter(|word| word.start.is_none() && word.end.is_none())
+                .count(),
+        }
+    }
+}
+
+/// Print statistics about the index, either readably or as the JSON that `/stats` returns
+pub fn command_stats(collection: Option<String>, top_words: usize, json: bool) -> HskResult<()> {
+    let filter = TranscriptFilter::collection(collection);
+    filter.validate()?;
+    let mut timer = Timer::new();
+    let searcher = Searcher::load();
+    // the JSON is all that is printed, so that it can be read as is
+    if !json {
+        timer.print("Searcher loaded");
+    }
+    let stats = searcher.stats(&filter, top_words);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    println!("Transcripts: {}", stats.transcript_count);
+    println!("Words: {}", stats.total_words);
+    println!("Hours: {:.2}", stats.total_hours);
+    println!("Vocabulary: {} words", stats.vocabulary_size);
+    println!("Words without timestamps: {}", stats.untimed_words);
+    println!(
+        "Memory: ~{:.1} MiB (words {:.1}, postings {:.1}, vocabulary {:.1}, other {:.1})",
+        mebibytes(stats.memory.total),
+        mebibytes(stats.memory.transcript_words),
+        mebibytes(stats.memory.postings),
+        mebibytes(stats.memory.vocabulary),
+        mebibytes(stats.memory.other),
+    );
+
+    println!("\nTop words:");
+    for (word, count) in stats.top_words.iter() {
+        println!("  {count:>8}  {word}");
+    }
+
+    println!("\nTranscripts:");
+    for (id, transcript) in stats.transcripts.iter() {
+        let duration = transcript
+            .duration
+            .map(|duration| format!("{:.1} min", duration / 60.0))
+            .unwrap_or_else(|| String::from("untimed"));
+        println!(
+            "  [{id}] {}: {} words, {duration}",
+            transcript.path, transcript.words
+        );
+    }
+    Ok(())
+}
+
+fn mebibytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::searcher::tests::searcher_with;
+    use crate::synonyms::Synonyms;
+
+    #[test]
+    fn counts_the_words_of_the_filtered_transcripts() {
+        let searcher = searcher_with(
+            &["grace upon grace", "the grace of god", "the word"],
+            &["the", "of"],
+            Synonyms::default(),
+        );
+        let stats = searcher.stats(&TranscriptFilter::default(), 2);
+        assert_eq!(stats.transcript_count, 3);
+        assert_eq!(stats.total_words, 9);
+        assert_eq!(stats.vocabulary_size, 6);
+        // stop words are left out, and ties are in alphabetical order
+        assert_eq!(
+            stats.top_words,
+            vec![("grace".to_string(), 3), ("god".to_string(), 1)]
+        );
+        assert_eq!(stats.untimed_words, 0);
+        assert_eq!(stats.transcripts[&1].duration, Some(4.0));
+        assert!((stats.total_hours - 9.0 / 3600.0).abs() < 1e-9);
+
+        let filter = TranscriptFilter {
+            include: vec![2],
+            ..Default::default()
+        };
+        let stats = searcher.stats(&filter, 2);
+        assert_eq!(stats.transcript_count, 1);
+        assert_eq!(stats.total_words, 2);
+        assert_eq!(stats.vocabulary_size, 2);
+        assert_eq!(stats.top_words, vec![("word".to_string(), 1)]);
+        assert_eq!(stats.transcripts[&2].path, "2");
+    }
+}
@@ -0,0 +1,127 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rust_stemmers::Algorithm;
+
+/// Endings are only removed when at least this many letters are left
+const MIN_STEM_LENGTH: usize = 3;
+
+static SNOWBALL_ENGLISH: Lazy<rust_stemmers::Stemmer> =
+    Lazy::new(|| rust_stemmers::Stemmer::create(Algorithm::English));
+
+/**
+Reduces words to a shared stem, so that "justified", "justify" and "justification" are all "justifi"
+- [`Stemmer::English`] is the [Snowball](https://snowballstem.org/algorithms/english/stemmer.html)
+  English stemmer, which removes at most one suffix in each of its steps and keeps the stem long
+  enough that "justice", "just" and "heavy" don't end up with the same stem as "justify" or "heaven"
+- [`Stemmer::Endings`] removes the longest of the config's own word endings instead
+*/
+#[derive(Clone, Debug, Default)]
+pub enum Stemmer {
+    #[default]
+    English,
+    /// Regular expressions (one per line in the config) matched at the end of a word, such as
+    /// `ations?`
+    Endings(Vec<Regex>),
+}
+
+impl Stemmer {
+    /// Lines that aren't valid regular expressions are left out
+    pub fn new(word_endings: &str) -> Self {
+        let endings = word_endings
+            .lines()
+            .map(str::trim)
+            .filter(|ending| !ending.is_empty())
+            .filter_map(|ending| Regex::new(&format!("(?:{ending})$")).ok())
+            .collect();
+        Self::Endings(endings)
+    }
+
+    /**
+    - `word` is already normalized (see [`normalize_word`](crate::searcher::normalize_word))
+    - With [`Stemmer::Endings`], only one ending is removed (the longest), turning a final `i` back into
+      the `y` that it came from (as in "justifi(ed)"), or else a final `e`, so that "love" and "loved"
+      are both "lov"
+    */
+    pub fn stem(&self, word: &str) -> String {
+        let endings = match self {
+            Self::English => return SNOWBALL_ENGLISH.stem(word).into_owned(),
+            Self::Endings(endings) => endings,
+        };
+        let mut stem = word.to_string();
+        if let Some(ending_start) = Self::longest_ending(endings, &stem) {
+            stem.truncate(ending_start);
+            if stem.ends_with('i') {
+                stem.pop();
+                stem.push('y');
+            }
+        } else if stem.len() > MIN_STEM_LENGTH && stem.ends_with('e') {
+            stem.pop();
+        }
+        stem
+    }
+
+    /// Where the longest ending that can be removed from `word` starts
+    fn longest_ending(endings: &[Regex], word: &str) -> Option<usize> {
+        endings
+            .iter()
+            .filter_map(|ending| ending.find(word))
+            .map(|found| found.start())
+            .filter(|start| *start >= MIN_STEM_LENGTH && *start < word.len())
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn english(word: &str) -> String {
+        Stemmer::English.stem(word)
+    }
+
+    #[test]
+    fn english_keeps_word_families_together() {
+        assert_eq!(english("justified"), english("justify"));
+        assert_eq!(english("justifies"), english("justify"));
+        assert_eq!(english("loved"), english("love"));
+        assert_eq!(english("loving"), english("love"));
+        assert_eq!(english("believers"), english("believer"));
+    }
+
+    #[test]
+    fn english_keeps_different_words_apart() {
+        let stems = [english("justify"), english("justice"), english("just")];
+        assert_ne!(stems[0], stems[1]);
+        assert_ne!(stems[0], stems[2]);
+        assert_ne!(stems[1], stems[2]);
+        assert_ne!(english("many"), english("man"));
+        assert_ne!(english("heaven"), english("heavy"));
+    }
+
+    #[test]
+    fn shipped_endings_keep_word_families_together() {
+        let stemmer = Stemmer::new(include_str!("../config/word_endings.txt"));
+        let stem = |word| stemmer.stem(word);
+        assert_eq!(stem("justified"), stem("justify"));
+        assert_eq!(stem("justifies"), stem("justify"));
+        assert_eq!(stem("loved"), stem("love"));
+        assert_eq!(stem("loving"), stem("love"));
+        assert_eq!(stem("believers"), stem("believer"));
+        assert_ne!(stem("justify"), stem("justice"));
+        assert_ne!(stem("justify"), stem("just"));
+        assert_ne!(stem("many"), stem("man"));
+        assert_ne!(stem("heaven"), stem("heavy"));
+    }
+
+    #[test]
+    fn endings_remove_one_ending() {
+        let stemmer = Stemmer::new("ness\nful\ned\ning");
+        // "ful" is left on once "ness" is removed
+        assert_eq!(stemmer.stem("faithfulness"), "faithful");
+        assert_eq!(stemmer.stem("carried"), "carry");
+        assert_eq!(stemmer.stem("love"), "lov");
+        // too little would be left
+        assert_eq!(stemmer.stem("red"), "red");
+        assert_eq!(stemmer.stem("sing"), "sing");
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use crate::searcher::normalize_word;
+
+/**
+Groups of words and phrases that can stand in for each other in a search, read from a file like
+```text
+# Christ, Messiah and Jesus are interchangeable
+christ, messiah, jesus
+holy spirit, holy ghost
+```
+- Each line is a group, separated by commas, and lines starting with `#` are comments
+- A word or phrase in more than one group only belongs to the first
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Synonyms {
+    /// Each entry is the normalized words of a word or phrase
+    groups: Vec<Vec<Vec<String>>>,
+    /// Entry -> the group it is in
+    group_of: HashMap<Vec<String>, usize>,
+}
+
+impl Synonyms {
+    pub fn new(contents: &str) -> Self {
+        let mut synonyms = Self::default();
+        for line in contents.lines().map(str::trim) {
+            if line.starts_with('#') {
+                continue;
+            }
+            let group: Vec<Vec<String>> = line
+                .split(',')
+                .map(|entry| {
+                    entry
+                        .split_whitespace()
+                        .map(normalize_word)
+                        .filter(|word| !word.is_empty())
+                        .collect::<Vec<String>>()
+                })
+                .filter(|entry| !entry.is_empty())
+                .collect();
+            if group.len() < 2 {
+                continue;
+            }
+            let idx = synonyms.groups.len();
+            for entry in group.iter() {
+                synonyms.group_of.entry(entry.clone()).or_insert(idx);
+            }
+            synonyms.groups.push(group);
+        }
+        synonyms
+    }
+
+    /// Every word or phrase (including `words` itself) in the group that `words` is in
+    pub fn group(&self, words: &[String]) -> Option<&[Vec<String>]> {
+        let idx = self.group_of.get(words)?;
+        Some(&self.groups[*idx])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::searcher::{
+        tests::{found_in, searcher_with},
+        QueryParams,
+    };
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    fn group(synonyms: &Synonyms, text: &str) -> Option<Vec<String>> {
+        let group = synonyms.group(&words(text))?;
+        Some(group.iter().map(|entry| entry.join(" ")).collect())
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let synonyms = Synonyms::new(
+            "# christ, messiah\n\n  \n  # holy spirit, holy ghost\nlamb, , Passover Lamb\nalone\n",
+        );
+        assert_eq!(group(&synonyms, "christ"), None);
+        assert_eq!(group(&synonyms, "holy ghost"), None);
+        // entries are normalized, and empty ones are left out
+        assert_eq!(
+            group(&synonyms, "passover lamb"),
+            Some(vec!["lamb".to_string(), "passover lamb".to_string()])
+        );
+        // a group needs more than one entry
+        assert_eq!(group(&synonyms, "alone"), None);
+    }
+
+    #[test]
+    fn words_in_several_groups_belong_to_the_first() {
+        let synonyms = Synonyms::new("christ, messiah, jesus\nlord, jesus\n");
+        assert_eq!(
+            group(&synonyms, "jesus"),
+            Some(words("christ messiah jesus"))
+        );
+        // the later group still has it
+        assert_eq!(group(&synonyms, "lord"), Some(words("lord jesus")));
+    }
+
+    #[test]
+    fn searches_find_synonyms() {
+        let searcher = searcher_with(
+            &[
+                "the messiah has come",
+                "the holy ghost fell",
+                "the lord is here",
+            ],
+            &[],
+            Synonyms::new("christ, messiah\nholy spirit, holy ghost\n"),
+        );
+        let found_in = |query: &str, synonyms: bool| {
+            let params = QueryParams {
+                query: query.to_string(),
+                synonyms,
+                ..Default::default()
+            };
+            found_in(&searcher, params)
+        };
+        assert!(found_in("christ", false).is_empty());
+        assert_eq!(found_in("christ", true), ["0".to_string()].into());
+        assert_eq!(found_in("\"holy spirit\"", true), ["1".to_string()].into());
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::hsk_file::Word;
+
+pub type TokenId = u32;
+
+/**
+Every distinct word across all transcripts, stored once: both as it was written (not normalized) and
+as it is indexed (see [`index_tokens`](crate::hsk_file::index_tokens))
+- Transcripts refer to their words by [`TokenId`] instead of holding their own copies, as does the
+  index of the [`Searcher`](crate::searcher::Searcher)
+- Each word counts its uses, and is removed once it has none, with its id given to the next new word
+- Copies share the words they have in common, like a [`SharedMap`](crate::searcher::SharedMap)
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Vocabulary {
+    /// The word with each id (empty for the ids in `free`)
+    tokens: im::Vector<Arc<str>>,
+    /// How many times the word with each id was interned and not released since
+    uses: im::Vector<u32>,
+    /// Sorted, so words can be found by how they start
+    ids: im::OrdMap<Arc<str>, TokenId>,
+    /// The ids of removed words, to be given out again
+    free: im::Vector<TokenId>,
+}
+
+impl Vocabulary {
+    /// The id of `token`, adding it if it is new, and counting one more use of it
+    pub fn intern(&mut self, token: &str) -> TokenId {
+        if let Some(&id) = self.ids.get(token) {
+            self.uses[id as usize] += 1;
+            return id;
+        }
+        let token: Arc<str> = Arc::from(token);
+        let id = match self.free.pop_back() {
+            Some(id) => {
+                self.tokens.set(id as usize, token.clone());
+                self.uses.set(id as usize, 1);
+                id
+            }
+            None => {
+                self.tokens.push_back(token.clone());
+                self.uses.push_back(1);
+                (self.tokens.len() - 1) as TokenId
+            }
+        };
+        self.ids.insert(token, id);
+        id
+    }
+
+    /// Count one less use of the word with `id` (see [`Vocabulary::intern`]), removing it once it has
+    /// none
+    pub fn release(&mut self, id: TokenId) {
+        let uses = &mut self.uses[id as usize];
+        debug_assert!(
+            *uses > 0,
+            "Token {id} was released more than it was interned"
+        );
+        *uses = uses.saturating_sub(1);
+        if *uses == 0 {
+            let token = self.tokens.set(id as usize, Arc::from(""));
+            self.ids.remove(&token);
+            self.free.push_back(id);
+        }
+    }
+
+    pub fn get(&self, id: TokenId) -> &str {
+        &self.tokens[id as usize]
+    }
+
+    pub fn id(&self, token: &str) -> Option<TokenId> {
+        self.ids.get(token).copied()
+    }
+
+    /// Every word starting with `prefix` (every word when it is empty), in order
+    pub fn starting_with<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a str, TokenId)> + 'a {
+        self.ids
+            .range::<_, str>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |(token, _)| token.starts_with(prefix))
+            .map(|(token, id)| (&**token, *id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Roughly how many bytes the words and their lookup table take up
+    pub fn heap_size(&self) -> usize {
+        let words: usize = self.tokens.iter().map(|token| token.len()).sum();
+        let tokens = self.tokens.len() * std::mem::size_of::<Arc<str>>();
+        let uses = self.uses.len() * std::mem::size_of::<u32>();
+        let ids = self.ids.len() * std::mem::size_of::<(Arc<str>, TokenId)>();
+        words + tokens + uses + ids
+    }
+
+    /// The words of a transcript, with each distinct word interned once (see
+    /// [`Vocabulary::release_words`])
+    pub fn index_words(&mut self, words: Vec<Word>) -> Vec<IndexedWord> {
+        let mut ids: HashMap<String, TokenId> = HashMap::new();
+        words
+            .into_iter()
+            .map(|word| IndexedWord {
+                token: *ids
+                    .entry(word.word)
+                    .or_insert_with_key(|token| self.intern(token)),
+                start: word.start,
+                end: word.end,
+            })
+            .collect()
+    }
+
+    /// Release the words of a transcript that [`Vocabulary::index_words`] interned
+    pub fn release_words(&mut self, words: &[IndexedWord]) {
+        let tokens: BTreeSet<TokenId> = words.iter().map(|word| word.token).collect();
+        for token in tokens {
+            self.release(token);
+        }
+    }
+
+    pub fn word(&self, word: &IndexedWord) -> Word {
+        Word {
+            word: self.get(word.token).to_string(),
+            start: word.start,
+            end: word.end,
+        }
+    }
+}
+
+/// A [`Word`] as it is kept in memory, with its text in the [`Vocabulary`]
+#[derive(Copy, Clone, Debug)]
+pub struct IndexedWord {
+    pub token: TokenId,
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_removed_once_unused() {
+        let mut vocabulary = Vocabulary::default();
+        let grace = vocabulary.intern("grace");
+        assert_eq!(vocabulary.intern("grace"), grace);
+        vocabulary.release(grace);
+        assert_eq!(vocabulary.id("grace"), Some(grace));
+        vocabulary.release(grace);
+        assert_eq!(vocabulary.id("grace"), None);
+        assert!(vocabulary.is_empty());
+        // the id is given to the next new word
+        assert_eq!(vocabulary.intern("mercy"), grace);
+        assert_eq!(vocabulary.get(grace), "mercy");
+    }
+
+    #[test]
+    fn copies_keep_their_own_words() {
+        let mut vocabulary = Vocabulary::default();
+        let grace = vocabulary.intern("grace");
+        let copy = vocabulary.clone();
+        vocabulary.release(grace);
+        vocabulary.intern("mercy");
+        assert_eq!(copy.get(grace), "grace");
+        assert_eq!(copy.id("mercy"), None);
+    }
+
+    #[test]
+    fn finds_words_by_how_they_start() {
+        let mut vocabulary = Vocabulary::default();
+        for word in ["redeemed", "red", "redeem", "rest", "Redeemer"] {
+            vocabulary.intern(word);
+        }
+        let words = |prefix| {
+            vocabulary
+                .starting_with(prefix)
+                .map(|(word, _)| word)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(words("redee"), vec!["redeem", "redeemed"]);
+        assert_eq!(words("x"), Vec::<&str>::new());
+        assert_eq!(words("").len(), 5);
+    }
+
+    #[test]
+    fn transcript_words_are_interned_once() {
+        let mut vocabulary = Vocabulary::default();
+        let word = |word: &str| Word {
+            word: word.to_string(),
+            start: None,
+            end: None,
+        };
+        let words = vocabulary.index_words(vec![word("grace"), word("upon"), word("grace")]);
+        assert_eq!(words[0].token, words[2].token);
+        vocabulary.release_words(&words);
+        assert!(vocabulary.is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+use walkdir::WalkDir;
+
+use crate::app_config::APP_EXT;
+use crate::convert::transcript_file_path;
+use crate::hsk_file::HskResult;
+use crate::searcher::{is_transcript_file, Map, Searcher};
+use crate::utils::Timer;
+use crate::{CONFIG, SEARCHER};
+
+/// How long to wait for a burst of file events (such as a whole directory being converted) to
+/// finish before reloading
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Files that the server itself just wrote (or deleted) and reloaded, with when they were last
+/// modified afterwards (`None` once deleted), so that the watcher doesn't reload them a second time
+static OWN_CHANGES: Lazy<Mutex<Map<PathBuf, Option<SystemTime>>>> = Lazy::new(Default::default);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/**
+Held while a new [`Searcher`] is built and swapped in, so that two reloads at once (such as an upload
+and a change seen by the watcher) can't each start from the same [`Searcher`] and leave out the
+other's changes
+- Reloads re-read files, so they wait for each other instead of being retried with
+  [`ArcSwap::rcu`](arc_swap::ArcSwap::rcu)
+*/
+static RELOADING: Mutex<()> = Mutex::new(());
+
+/// Reload every transcript from disk and swap it in for the current [`SEARCHER`]
+pub fn reload_all() -> Arc<Searcher> {
+    let _reloading = RELOADING.lock().unwrap_or_else(|err| err.into_inner());
+    let mut timer = Timer::new();
+    let searcher = Arc::new(SEARCHER.load().reloaded_all());
+    SEARCHER.store(searcher.clone());
+    timer.print(
+        format!(
+            "Searcher reloaded {} transcripts",
+            searcher.transcript_words.len()
+        )
+        .as_str(),
+    );
+    searcher
+}
+
+/// Re-read only the given `.hsk` files and swap the result in for the current [`SEARCHER`]
+/// - Searches that are already running keep using the previous [`Searcher`]
+pub fn reload_paths(paths: &[PathBuf]) -> Arc<Searcher> {
+    let _reloading = RELOADING.lock().unwrap_or_else(|err| err.into_inner());
+    let mut timer = Timer::new();
+    let searcher = Arc::new(SEARCHER.load().reloaded(paths));
+    SEARCHER.store(searcher.clone());
+    timer.print(format!("Searcher reloaded {} changed files", paths.len()).as_str());
+    searcher
+}
+
+/**
+Like [`reload_paths`], for files that were just written (or deleted) by the server itself
+- The watcher still reloads them if they are changed again before it sees them
+*/
+pub fn reload_own_changes(paths: &[PathBuf]) -> Arc<Searcher> {
+    let mut own_changes = OWN_CHANGES.lock().unwrap_or_else(|err| err.into_inner());
+    for path in paths {
+        own_changes.insert(path.clone(), modified(path));
+    }
+    drop(own_changes);
+    reload_paths(paths)
+}
+
+/// Whether `path` is still as the server left it (see [`reload_own_changes`]), which is only
+/// checked once
+fn is_own_change(path: &Path) -> bool {
+    let mut own_changes = OWN_CHANGES.lock().unwrap_or_else(|err| err.into_inner());
+    own_changes
+        .remove(path)
+        .is_some_and(|left_at| left_at == modified(path))
+}
+
+/// Files being opened or read (including by reloading them) are reported too, which aren't changes
+fn changes_files(event: &notify::Result<Event>) -> bool {
+    event.as_ref().is_ok_and(|event| match event.kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Access(_) => false,
+        _ => true,
+    })
+}
+
+/// Whether `event` can add or remove whole directories (such as by renaming or moving one)
+fn moves_paths(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+    )
+}
+
+/**
+The `.hsk` files that a directory being added or removed at `path` adds or removes
+- Those in a directory that is there now are (re)loaded, and the transcripts that were loaded from
+  one that is gone are removed
+*/
+fn transcript_files_in(path: &Path) -> Vec<PathBuf> {
+    if path.is_dir() {
+        WalkDir::new(path)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .map(|entry| entry.into_path())
+            .filter(|path| is_transcript_file(path))
+            .collect()
+    } else if path.exists() {
+        vec![]
+    } else {
+        SEARCHER
+            .load()
+            .transcript_paths
+            .values()
+            .filter_map(|transcript| transcript_file_path(transcript).ok())
+            .filter(|file| file.starts_with(path))
+            .collect()
+    }
+}
+
+/**
+Watch the data directories and incrementally update [`SEARCHER`] as `.hsk` files are added, replaced or
+removed
+- Directories that are added, renamed, moved or removed reload the transcripts in them
+- The watcher stops when the returned value is dropped
+*/
+pub fn watch_data_dir() -> HskResult<RecommendedWatcher> {
+    let (sender, receiver) = channel::<notify::Result<Event>>();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for (_, root) in CONFIG.transcript_roots() {
+        if root.is_dir() {
+            watcher.watch(&root, RecursiveMode::Recursive)?;
+        }
+    }
+
+    std::thread::spawn(move || {
+        while let Ok(event) = receiver.recv() {
+            let mut paths: Vec<PathBuf> = vec![];
+            let mut directories: Vec<PathBuf> = vec![];
+            let mut event = Some(event);
+            // collect everything that happens until things settle down
+            loop {
+                if let Some(Ok(event)) = event.take().filter(changes_files) {
+                    let moves_paths = moves_paths(&event);
+                    for path in event.paths {
+                        if path.extension().is_some_and(|ext| ext == APP_EXT) {
+                            paths.push(path);
+                        } else if moves_paths {
+                            directories.push(path);
+                        }
+                    }
+                }
+                match receiver.recv_timeout(DEBOUNCE) {
+                    Ok(next) => event = Some(next),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            for directory in directories {
+                paths.extend(transcript_files_in(&directory));
+            }
+            paths.sort();
+            paths.dedup();
+            paths.retain(|path| !is_own_change(path));
+            if !paths.is_empty() {
+                reload_paths(&paths);
+            }
+        }
+    });
+
+    Ok(watcher)
+}
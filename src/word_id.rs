@@ -1,49 +1,108 @@
-use std::ops::{BitOr, Deref};
-
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+/// Which word of the query (by its position) a word index was found for
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct WordId(u32);
 
 impl WordId {
     pub fn from_index(n: usize) -> Self {
-        Self(1_u32 << n)
+        Self(n as u32)
     }
 
     pub fn to_index(&self) -> usize {
-        self.0.trailing_zeros() as usize
+        self.0 as usize
     }
-}
-
-impl Deref for WordId {
-    type Target = u32;
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    /// Which block of a [`WordIdSet`] this is in, and its bit in that block
+    fn block_and_bit(&self) -> (usize, u64) {
+        let index = self.to_index();
+        (index / BLOCK_BITS, 1_u64 << (index % BLOCK_BITS))
     }
 }
 
-impl BitOr for WordId {
-    type Output = WordId;
-
-    fn bitor(self, rhs: Self) -> Self::Output {
-        WordId(self.0 | rhs.0)
-    }
-}
+const BLOCK_BITS: usize = u64::BITS as usize;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-pub struct WordIdSet(WordId);
+/// A set of [`WordId`]s, which grows to fit however many words a query has
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WordIdSet(Vec<u64>);
 
 impl WordIdSet {
     pub fn new(word_id: WordId) -> Self {
-        Self(word_id)
+        let mut set = Self::default();
+        set.add(word_id);
+        set
     }
 
     pub fn unique_count(&self) -> usize {
-        self.0.count_ones() as usize
+        self.0.iter().map(|block| block.count_ones() as usize).sum()
     }
 
     pub fn add(&mut self, word_id: WordId) {
-        self.0 = self.0 | word_id
+        let (block, bit) = word_id.block_and_bit();
+        if block >= self.0.len() {
+            self.0.resize(block + 1, 0);
+        }
+        self.0[block] |= bit;
+    }
+
+    pub fn contains(&self, word_id: WordId) -> bool {
+        let (block, bit) = word_id.block_and_bit();
+        self.0.get(block).is_some_and(|block| block & bit != 0)
+    }
+
+    /// Whether every word in `word_ids` is in this set
+    pub fn contains_all(&self, word_ids: &WordIdSet) -> bool {
+        word_ids.0.iter().enumerate().all(|(idx, required)| {
+            let block = self.0.get(idx).copied().unwrap_or_default();
+            block & required == *required
+        })
+    }
+}
+
+impl FromIterator<WordId> for WordIdSet {
+    fn from_iter<T: IntoIterator<Item = WordId>>(iter: T) -> Self {
+        let mut set = Self::default();
+        for word_id in iter {
+            set.add(word_id);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ids: &[usize]) -> WordIdSet {
+        ids.iter().map(|id| WordId::from_index(*id)).collect()
+    }
+
+    #[test]
+    fn holds_ids_past_the_first_block() {
+        let mut set = WordIdSet::new(WordId::from_index(3));
+        for id in [31, 32, 63, 64, 130, 64] {
+            set.add(WordId::from_index(id));
+        }
+        for id in [3, 31, 32, 63, 64, 130] {
+            assert!(set.contains(WordId::from_index(id)), "{id} was added");
+        }
+        for id in [0, 33, 65, 129, 131, 500] {
+            assert!(!set.contains(WordId::from_index(id)), "{id} wasn't added");
+        }
+        // 64 was added twice
+        assert_eq!(set.unique_count(), 6);
+    }
+
+    #[test]
+    fn combines_sets_across_blocks() {
+        let low = [1, 33];
+        let high = [33, 64, 100];
+        let union = set(&[&low[..], &high[..]].concat());
+        assert_eq!(union.unique_count(), 4);
+        assert!(union.contains_all(&set(&low)));
+        assert!(union.contains_all(&set(&high)));
+        assert!(!set(&low).contains_all(&set(&high)));
+        assert!(!set(&[1]).contains_all(&set(&[64])));
+        assert!(set(&[64]).contains_all(&set(&[])));
     }
 }